@@ -0,0 +1,34 @@
+/// A ring buffer that delays a signal by a variable number of samples, up to the length it was
+/// allocated with.
+pub struct DelayLine {
+    buffer: Vec<f32>,
+    pos: usize,
+}
+
+impl DelayLine {
+    pub fn new(max_delay_samples: usize) -> Self {
+        Self {
+            buffer: vec![0.0; max_delay_samples + 1],
+            pos: 0,
+        }
+    }
+
+    /// Writes `input` to the delay line and returns the sample from `delay_samples` samples ago.
+    /// The delay is clamped to the delay line's maximum delay.
+    pub fn process(&mut self, input: f32, delay_samples: usize) -> f32 {
+        let len = self.buffer.len();
+        let delay_samples = delay_samples.min(len - 1);
+
+        self.buffer[self.pos] = input;
+        let output = self.buffer[(self.pos + len - delay_samples) % len];
+        self.pos = (self.pos + 1) % len;
+
+        output
+    }
+
+    /// Clears the delayed samples so no stale audio is heard after a reset.
+    pub fn reset(&mut self) {
+        self.buffer.fill(0.0);
+        self.pos = 0;
+    }
+}
@@ -0,0 +1,953 @@
+use nih_plug::prelude::*;
+use std::f32::consts::PI;
+
+use crate::meter::AtomicF32;
+
+/// The number of points in the custom envelope's lookup table.
+pub const CUSTOM_CURVE_POINTS: usize = 64;
+/// The number of intervals the envelope table divides a cycle into. This is fine enough that
+/// interpolating between the points stays within a small fraction of a decibel of the exact curve
+/// for all but the most extreme `bias` settings.
+pub const ENVELOPE_TABLE_POINTS: usize = 4096;
+/// How many of the envelope table's intervals after the end of the hold are computed directly
+/// instead. Biases below 0.5 make the curve infinitely steep there, which interpolation can't
+/// follow.
+const ENVELOPE_TABLE_DIRECT_INTERVALS: f32 = 4.0;
+
+/// The shape of the ducking curve over a single cycle.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvShape {
+    #[id = "exponential"]
+    Exponential,
+    #[id = "linear"]
+    Linear,
+    #[id = "sine"]
+    Sine,
+    #[id = "triangle"]
+    Triangle,
+    #[id = "logarithmic"]
+    Logarithmic,
+}
+
+/// Computes the gain change in decibels for a ducking envelope with the given shape. `phase` is the
+/// normalized position within a cycle that lasts `length` beats, with the deepest reduction at
+/// `phase == 0.0`. `release_pow` controls the steepness of the recovery out of the duck, and
+/// `attack_pow` controls the steepness of the descent into the next duck. Only the sine and
+/// triangle shapes have one, the others snap back into the duck at the end of the cycle and ignore
+/// it. `amount` scales the maximum reduction of `depth_db` decibels.
+pub fn shape_gain(
+    shape: EnvShape,
+    phase: f32,
+    length: f32,
+    attack_pow: f32,
+    release_pow: f32,
+    amount: f32,
+    depth_db: f32,
+) -> f32 {
+    let depth = match shape {
+        // This is the original curve, which recovers over beats rather than over the cycle
+        EnvShape::Exponential => (phase * length + 1.0).powf(-release_pow),
+        EnvShape::Linear => 1.0 - phase,
+        EnvShape::Sine => bend_halves(
+            0.5 * (1.0 + (2.0 * PI * phase).cos()),
+            phase,
+            attack_pow,
+            release_pow,
+        ),
+        EnvShape::Triangle => {
+            bend_halves((1.0 - 2.0 * phase).abs(), phase, attack_pow, release_pow)
+        }
+        // The mirror image of the exponential curve, holding the duck for most of the cycle
+        // before snapping back
+        EnvShape::Logarithmic if release_pow > 0.0 => {
+            (1.0 + (1.0 - phase) * (release_pow.exp() - 1.0)).ln() / release_pow
+        }
+        EnvShape::Logarithmic => 1.0 - phase,
+    };
+
+    -depth.clamp(0.0, 1.0) * depth_db * amount
+}
+
+/// The settings that shape the tempo synced ducking envelope within a single cycle.
+#[derive(Clone, Copy)]
+pub struct DuckEnvelope<'a> {
+    pub shape: EnvShape,
+    /// Replaces the shape with a user drawn curve when set.
+    pub custom_curve: Option<&'a CustomCurve>,
+    pub attack_pow: f32,
+    pub release_pow: f32,
+    pub amount: f32,
+    pub depth_db: f32,
+    pub hold: f32,
+    pub bias: f32,
+}
+
+/// Computes the tempo synced envelope's gain change in decibels at the normalized `phase` within a
+/// cycle that lasts `cycle_beats` beats. This applies the hold and bias before `shape_gain()`, and
+/// a cycle without a length leaves the gain unchanged.
+pub fn compute_duck_db(envelope: &DuckEnvelope, phase: f32, cycle_beats: f32) -> f32 {
+    if cycle_beats <= 0.0 {
+        return 0.0;
+    }
+
+    let phase = apply_hold(phase, envelope.hold);
+    let phase = apply_bias(phase, envelope.bias);
+    match envelope.custom_curve {
+        Some(curve) => -curve.depth(phase) * envelope.depth_db * envelope.amount,
+        None => shape_gain(
+            envelope.shape,
+            phase,
+            cycle_beats,
+            envelope.attack_pow,
+            envelope.release_pow,
+            envelope.amount,
+            envelope.depth_db,
+        ),
+    }
+}
+
+/// The settings an [`EnvelopeTable`] was computed for. The amount and depth only scale the curve,
+/// so they're applied after the lookup instead.
+#[derive(Clone, Copy, PartialEq)]
+struct EnvelopeTableKey {
+    shape: EnvShape,
+    attack_pow: f32,
+    release_pow: f32,
+    hold: f32,
+    bias: f32,
+    cycle_beats: f32,
+}
+
+impl EnvelopeTableKey {
+    /// The key for `envelope`, or `None` for custom curves. Those are already a lookup table, and
+    /// the editor can change them at any time.
+    fn new(envelope: &DuckEnvelope, cycle_beats: f32) -> Option<Self> {
+        if envelope.custom_curve.is_some() {
+            return None;
+        }
+
+        Some(Self {
+            shape: envelope.shape,
+            attack_pow: envelope.attack_pow,
+            release_pow: envelope.release_pow,
+            hold: envelope.hold,
+            bias: envelope.bias,
+            cycle_beats,
+        })
+    }
+}
+
+/// The envelope's gain change across a single cycle, computed ahead of time so `process()` can
+/// interpolate between the table's points instead of evaluating the shape's `powf()` calls for
+/// every sample. The table is only used while the envelope's settings match the ones it was
+/// computed for. Everything else, like settings that are still being smoothed towards a new
+/// value, falls back to [`compute_duck_db()`].
+pub struct EnvelopeTable {
+    key: Option<EnvelopeTableKey>,
+    /// The gain change at full amount and a depth of 1 dB, at `ENVELOPE_TABLE_POINTS + 1` evenly
+    /// spaced phases that include both the start and the end of the cycle. The shapes aren't
+    /// continuous across the cycle boundary, so the end can't wrap around to the start.
+    points: Vec<f32>,
+}
+
+impl Default for EnvelopeTable {
+    fn default() -> Self {
+        Self {
+            key: None,
+            points: vec![0.0; ENVELOPE_TABLE_POINTS + 1],
+        }
+    }
+}
+
+impl EnvelopeTable {
+    /// Recomputes the table for `envelope` if it was computed for different settings. This doesn't
+    /// allocate, so it can be called from the audio thread at the start of a block.
+    pub fn update(&mut self, envelope: &DuckEnvelope, cycle_beats: f32) {
+        let key = EnvelopeTableKey::new(envelope, cycle_beats);
+        if key.is_none() || key == self.key {
+            return;
+        }
+
+        let unit_envelope = DuckEnvelope {
+            amount: 1.0,
+            depth_db: 1.0,
+            ..*envelope
+        };
+        for (i, point) in self.points.iter_mut().enumerate() {
+            let phase = i as f32 / ENVELOPE_TABLE_POINTS as f32;
+            *point = compute_duck_db(&unit_envelope, phase, cycle_beats);
+        }
+        self.key = key;
+    }
+
+    /// The same as [`compute_duck_db()`], but looked up from the table when the table was
+    /// computed for these settings.
+    pub fn duck_db(&self, envelope: &DuckEnvelope, phase: f32, cycle_beats: f32) -> f32 {
+        let key = EnvelopeTableKey::new(envelope, cycle_beats);
+        let after_hold = (phase - envelope.hold) * ENVELOPE_TABLE_POINTS as f32;
+        if key.is_none()
+            || key != self.key
+            || (0.0..ENVELOPE_TABLE_DIRECT_INTERVALS).contains(&after_hold)
+        {
+            return compute_duck_db(envelope, phase, cycle_beats);
+        }
+
+        let pos = phase.clamp(0.0, 1.0) * ENVELOPE_TABLE_POINTS as f32;
+        let idx = (pos as usize).min(ENVELOPE_TABLE_POINTS - 1);
+        let a = self.points[idx];
+        let b = self.points[idx + 1];
+
+        (a + (b - a) * (pos - idx as f32)) * envelope.depth_db * envelope.amount
+    }
+}
+
+/// A user drawn envelope shape, stored as `CUSTOM_CURVE_POINTS` evenly spaced points across a
+/// cycle. Each point is the depth of the duck at that point, from 0 for no reduction to 1 for the
+/// full depth. The editor writes the table while the audio thread reads it without locking or
+/// allocating. A block processed while the curve is being replaced may see a mix of the old and
+/// new points, which is no different from the user still drawing the curve.
+pub struct CustomCurve {
+    points: Vec<AtomicF32>,
+}
+
+impl Default for CustomCurve {
+    /// A linear recovery from the full depth, like the linear shape.
+    fn default() -> Self {
+        Self {
+            points: (0..CUSTOM_CURVE_POINTS)
+                .map(|i| AtomicF32::new(1.0 - i as f32 / CUSTOM_CURVE_POINTS as f32))
+                .collect(),
+        }
+    }
+}
+
+impl CustomCurve {
+    /// Replaces the curve with `points`, which are also evenly spaced across a cycle and get
+    /// resampled to `CUSTOM_CURVE_POINTS` points. Values are clamped to the 0-1 range, and NaN
+    /// values are treated as 0. An empty slice leaves the curve unchanged.
+    pub fn set(&self, points: &[f32]) {
+        if points.is_empty() {
+            return;
+        }
+
+        for (i, point) in self.points.iter().enumerate() {
+            let depth = interpolate_cyclic(points, i as f32 / CUSTOM_CURVE_POINTS as f32);
+            let depth = if depth.is_nan() { 0.0 } else { depth };
+            point.store(depth.clamp(0.0, 1.0));
+        }
+    }
+
+    /// The table's current points.
+    pub fn points(&self) -> Vec<f32> {
+        self.points.iter().map(AtomicF32::load).collect()
+    }
+
+    /// The depth at the normalized `phase` within the cycle, linearly interpolated between the
+    /// nearest points. The last point interpolates back towards the first point.
+    pub fn depth(&self, phase: f32) -> f32 {
+        let pos = phase.rem_euclid(1.0) * CUSTOM_CURVE_POINTS as f32;
+        let idx = (pos as usize).min(CUSTOM_CURVE_POINTS - 1);
+        let a = self.points[idx].load();
+        let b = self.points[(idx + 1) % CUSTOM_CURVE_POINTS].load();
+
+        a + (b - a) * (pos - idx as f32)
+    }
+}
+
+/// Linearly interpolates between evenly spaced `points` that span a cycle, at the normalized
+/// `phase`. `points` must not be empty.
+fn interpolate_cyclic(points: &[f32], phase: f32) -> f32 {
+    let pos = phase.rem_euclid(1.0) * points.len() as f32;
+    let idx = (pos as usize).min(points.len() - 1);
+    let a = points[idx];
+    let b = points[(idx + 1) % points.len()];
+
+    a + (b - a) * (pos - idx as f32)
+}
+
+/// The position in beats within a cycle that lasts `cycle_beats` beats, `second` seconds into the
+/// host's transport at `tempo` BPM. Pre-roll positions before the start of the transport wrap
+/// around to the end of the previous cycle.
+pub fn host_cycle_beat(tempo: f64, second: f64, cycle_beats: f64) -> f64 {
+    (tempo / 60.0 * second).rem_euclid(cycle_beats)
+}
+
+/// Advances a position within a cycle of `cycle_beats` beats by `seconds` at `beats_per_second`.
+/// Integrating the position like this instead of computing it from the transport position keeps
+/// the cycle continuous when the tempo changes.
+pub fn advance_beat(beat: f64, beats_per_second: f64, seconds: f64, cycle_beats: f64) -> f64 {
+    (beat + beats_per_second * seconds).rem_euclid(cycle_beats)
+}
+
+/// Swings every second cycle like an MPC's swing. `pair_phase` is the position within a pair of
+/// cycles, from 0 to 2. The second cycle starts `swing` cycles late, so the first cycle is
+/// stretched and the second one squeezed to make room. Returns the phase within the current cycle.
+pub fn swing_phase(pair_phase: f32, swing: f32) -> f32 {
+    let split = 1.0 + swing;
+    if pair_phase < split {
+        pair_phase / split
+    } else {
+        (pair_phase - split) / (2.0 - split)
+    }
+}
+
+/// Keeps the cycle from before a length change running for a short while so its gain can be
+/// crossfaded into the new cycle's gain, instead of jumping to a different point in the envelope.
+pub struct LengthFade {
+    /// The position within the old cycle in beats, advanced at the same rate as the new cycle.
+    beat: f64,
+    cycle_beats: f64,
+    remaining: usize,
+    len: usize,
+}
+
+/// The old cycle's state for a single sample during a `LengthFade`.
+#[derive(Debug, Clone, Copy)]
+pub struct FadingCycle {
+    /// The old cycle's normalized phase, including the phase offset.
+    pub phase: f32,
+    pub cycle_beats: f32,
+    /// How much of the old cycle's value is still used, going from 1 to 0 over the fade.
+    pub weight: f32,
+}
+
+impl LengthFade {
+    /// Starts fading out a cycle of `cycle_beats` beats that's currently at `beat`, over `len`
+    /// samples.
+    pub fn new(beat: f64, cycle_beats: f64, len: usize) -> Self {
+        Self {
+            beat,
+            cycle_beats,
+            remaining: len,
+            len: len.max(1),
+        }
+    }
+
+    /// The old cycle's state for the current sample, with the same `phase_offset` as the new
+    /// cycle.
+    pub fn cycle(&self, phase_offset: f32) -> FadingCycle {
+        let beat =
+            (self.beat + phase_offset as f64 * self.cycle_beats).rem_euclid(self.cycle_beats);
+        FadingCycle {
+            phase: (beat / self.cycle_beats) as f32,
+            cycle_beats: self.cycle_beats as f32,
+            weight: self.remaining as f32 / self.len as f32,
+        }
+    }
+
+    /// Advances the old cycle by one sample. Returns `false` once the fade has finished.
+    pub fn advance(&mut self, beats_per_second: f64, seconds: f64) -> bool {
+        self.beat = advance_beat(self.beat, beats_per_second, seconds, self.cycle_beats);
+        self.remaining = self.remaining.saturating_sub(1);
+        self.remaining > 0
+    }
+}
+
+impl FadingCycle {
+    /// Crossfades from the old cycle's `fading` value to the new cycle's `current` value.
+    pub fn blend(&self, current: f32, fading: f32) -> f32 {
+        current + (fading - current) * self.weight
+    }
+}
+
+/// Bends the recovering first half and the descending second half of a symmetric shape by
+/// `release_pow` and `attack_pow` respectively. The default power of 10 leaves the shape unchanged.
+fn bend_halves(depth: f32, phase: f32, attack_pow: f32, release_pow: f32) -> f32 {
+    let pow = if phase < 0.5 { release_pow } else { attack_pow };
+    depth.max(0.0).powf(pow / 10.0)
+}
+
+/// Remaps a cycle's phase so the envelope stays at its deepest point for the first `hold` fraction
+/// of the cycle and only then starts recovering over the remainder.
+pub fn apply_hold(phase: f32, hold: f32) -> f32 {
+    if phase < hold {
+        0.0
+    } else {
+        (phase - hold) / (1.0 - hold)
+    }
+}
+
+/// Warps a cycle's phase with a power curve so the midpoint of the envelope's shape lands at
+/// `bias` instead of halfway through the cycle. Lower values make the envelope recover sooner and
+/// take longer to descend into the next duck, higher values do the opposite. A bias of 0.5 leaves
+/// the phase unchanged.
+pub fn apply_bias(phase: f32, bias: f32) -> f32 {
+    let bias = bias.clamp(0.01, 0.99);
+    phase.max(0.0).powf(0.5f32.ln() / bias.ln())
+}
+
+/// The note value that a single step of the `length` parameter lasts for.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Division {
+    #[id = "whole"]
+    #[name = "1/1"]
+    Whole,
+    #[id = "half"]
+    #[name = "1/2"]
+    Half,
+    #[id = "quarter"]
+    #[name = "1/4"]
+    Quarter,
+    #[id = "eighth"]
+    #[name = "1/8"]
+    Eighth,
+    #[id = "sixteenth"]
+    #[name = "1/16"]
+    Sixteenth,
+    /// A whole bar, which depends on the host's time signature.
+    #[id = "bar"]
+    #[name = "Bar"]
+    Bar,
+}
+
+impl Division {
+    /// The length of this note value in quarter note beats.
+    pub fn beats(self, time_signature: TimeSignature) -> f64 {
+        match self {
+            Division::Whole => 4.0,
+            Division::Half => 2.0,
+            Division::Quarter => 1.0,
+            Division::Eighth => 0.5,
+            Division::Sixteenth => 0.25,
+            Division::Bar => time_signature.bar_beats(),
+        }
+    }
+}
+
+/// A time signature as reported by the host, used to find the length of a bar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeSignature {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+impl TimeSignature {
+    /// The time signature used until the host reports one.
+    pub const COMMON_TIME: TimeSignature = TimeSignature {
+        numerator: 4,
+        denominator: 4,
+    };
+
+    /// The length of a single bar in quarter note beats.
+    pub fn bar_beats(self) -> f64 {
+        self.numerator as f64 * 4.0 / self.denominator as f64
+    }
+}
+
+/// Scales the length of a `Division` to get triplet and dotted note values.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Modifier {
+    #[id = "straight"]
+    Straight,
+    #[id = "triplet"]
+    Triplet,
+    #[id = "dotted"]
+    Dotted,
+}
+
+impl Modifier {
+    /// The factor this modifier scales a note value's length by.
+    pub fn scale(self) -> f64 {
+        match self {
+            Modifier::Straight => 1.0,
+            Modifier::Triplet => 2.0 / 3.0,
+            Modifier::Dotted => 3.0 / 2.0,
+        }
+    }
+}
+
+/// Scales the host's tempo for a half-time or double-time feel without changing the division.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TempoMult {
+    #[id = "x0_25"]
+    #[name = "x0.25"]
+    Quarter,
+    #[id = "x0_5"]
+    #[name = "x0.5"]
+    Half,
+    #[id = "x1"]
+    #[name = "x1"]
+    Normal,
+    #[id = "x2"]
+    #[name = "x2"]
+    Double,
+    #[id = "x4"]
+    #[name = "x4"]
+    Quadruple,
+}
+
+impl TempoMult {
+    /// The factor this multiplier scales the host's tempo by.
+    pub fn factor(self) -> f64 {
+        match self {
+            TempoMult::Quarter => 0.25,
+            TempoMult::Half => 0.5,
+            TempoMult::Normal => 1.0,
+            TempoMult::Double => 2.0,
+            TempoMult::Quadruple => 4.0,
+        }
+    }
+}
+
+/// What sets the speed of the envelope's cycle. The free running modes set the duration of a
+/// whole cycle in Hz or milliseconds.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncMode {
+    #[id = "host_tempo"]
+    #[name = "Host Tempo"]
+    HostTempo,
+    #[id = "free_hz"]
+    #[name = "Free (Hz)"]
+    FreeHz,
+    #[id = "free_ms"]
+    #[name = "Free (ms)"]
+    FreeMs,
+}
+
+/// Where the tempo synced cycle starts from.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    /// Follows the host's transport position, so the cycle starts at the beginning of the song.
+    #[id = "song_start"]
+    #[name = "Song Start"]
+    SongStart,
+    /// Restarts the cycle when playback starts and every time the host's loop wraps around.
+    #[id = "loop_start"]
+    #[name = "Loop Start"]
+    LoopStart,
+    /// Keeps the cycle running without following the transport position.
+    #[id = "free"]
+    #[name = "Free"]
+    Free,
+}
+
+/// How often the ducking envelope is evaluated. The lower settings evaluate it every few samples
+/// and interpolate linearly in between, which skips most of the `powf()` calls the envelope's
+/// shape needs at the cost of lagging a few samples behind and rounding off the sharpest corners.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quality {
+    /// Evaluates the envelope for every sample.
+    #[id = "high"]
+    #[name = "High"]
+    High,
+    /// Evaluates the envelope once every 4 samples.
+    #[id = "normal"]
+    #[name = "Normal"]
+    Normal,
+    /// Evaluates the envelope once every 16 samples, about 0.3 ms at 48 kHz.
+    #[id = "eco"]
+    #[name = "Eco"]
+    Eco,
+}
+
+impl Quality {
+    /// How many samples apart the envelope is evaluated.
+    pub fn interval(self) -> usize {
+        match self {
+            Quality::High => 1,
+            Quality::Normal => 4,
+            Quality::Eco => 16,
+        }
+    }
+}
+
+/// The maximum boost an inverted envelope can apply, in decibels.
+pub const MAX_INVERTED_BOOST_DB: f32 = 12.0;
+
+/// The tremolo's LFO at the normalized `phase` within a cycle, from -1 to 1. The LFO is at its
+/// highest at the start of the cycle. The exponential and logarithmic shapes only make sense for a
+/// one-sided duck, so they use the sine instead.
+pub fn tremolo_lfo(shape: EnvShape, phase: f32) -> f32 {
+    match shape {
+        EnvShape::Sine | EnvShape::Exponential | EnvShape::Logarithmic => (2.0 * PI * phase).cos(),
+        EnvShape::Triangle => 2.0 * (1.0 - 2.0 * phase).abs() - 1.0,
+        EnvShape::Linear => 1.0 - 2.0 * phase,
+    }
+}
+
+/// Turns a gain reduction from `shape_gain()` or the sidechain follower into a boost of the same
+/// size, limited to `MAX_INVERTED_BOOST_DB`.
+pub fn invert_gain(gain_db: f32) -> f32 {
+    (-gain_db).min(MAX_INVERTED_BOOST_DB)
+}
+
+/// The gain change the ducking envelope's `gain_db` ends up applying. The reduction never goes
+/// below `floor_db`, unless `invert` turns it into a boost instead.
+pub fn limit_duck_db(gain_db: f32, floor_db: f32, invert: bool) -> f32 {
+    if invert {
+        invert_gain(gain_db)
+    } else {
+        gain_db.max(floor_db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFAULT_ENVELOPE: DuckEnvelope<'static> = DuckEnvelope {
+        shape: EnvShape::Exponential,
+        custom_curve: None,
+        attack_pow: 10.0,
+        release_pow: 10.0,
+        amount: 0.5,
+        depth_db: 50.0,
+        hold: 0.0,
+        bias: 0.5,
+    };
+
+    /// The gain change at `second` seconds into the transport for a cycle of `cycle_beats` beats.
+    fn duck_db_at(envelope: &DuckEnvelope, tempo: f64, second: f64, cycle_beats: f64) -> f32 {
+        if cycle_beats <= 0.0 {
+            return compute_duck_db(envelope, 0.0, 0.0);
+        }
+
+        let phase = host_cycle_beat(tempo, second, cycle_beats) / cycle_beats;
+        compute_duck_db(envelope, phase as f32, cycle_beats as f32)
+    }
+
+    /// The number of samples at `sample_rate` until a cycle of `cycle_beats` beats at `tempo` BPM
+    /// wraps around.
+    fn period_samples(tempo: f64, cycle_beats: f64, sample_rate: f64) -> usize {
+        let mut beat = 0.0;
+        (1..)
+            .find(|_| {
+                let next = advance_beat(beat, tempo / 60.0, 1.0 / sample_rate, cycle_beats);
+                let wrapped = next < beat;
+                beat = next;
+                wrapped
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn envelope_table_matches_direct_computation() {
+        let mut table = EnvelopeTable::default();
+        for shape in (0..EnvShape::variants().len()).map(EnvShape::from_index) {
+            for (hold, bias, release_pow) in [(0.0, 0.5, 10.0), (0.25, 0.3, 20.0), (0.1, 0.7, 5.0)]
+            {
+                let envelope = DuckEnvelope {
+                    shape,
+                    hold,
+                    bias,
+                    release_pow,
+                    ..DEFAULT_ENVELOPE
+                };
+                table.update(&envelope, 4.0);
+
+                for i in 0..1000 {
+                    let phase = i as f32 / 1000.0 + 0.000123;
+                    let expected = compute_duck_db(&envelope, phase, 4.0);
+                    let db = table.duck_db(&envelope, phase, 4.0);
+                    assert!(
+                        (db - expected).abs() < 0.1,
+                        "{shape:?} at {phase}: {db} != {expected}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn envelope_table_falls_back_for_other_settings() {
+        let mut table = EnvelopeTable::default();
+        table.update(&DEFAULT_ENVELOPE, 4.0);
+
+        // Settings that are still being smoothed and other cycle lengths are computed directly
+        let envelope = DuckEnvelope {
+            release_pow: 12.0,
+            ..DEFAULT_ENVELOPE
+        };
+        assert_eq!(
+            table.duck_db(&envelope, 0.1, 4.0),
+            compute_duck_db(&envelope, 0.1, 4.0)
+        );
+        assert_eq!(
+            table.duck_db(&DEFAULT_ENVELOPE, 0.1, 8.0),
+            compute_duck_db(&DEFAULT_ENVELOPE, 0.1, 8.0)
+        );
+    }
+
+    #[test]
+    fn deepest_at_cycle_start() {
+        let db = duck_db_at(&DEFAULT_ENVELOPE, 120.0, 0.0, 4.0);
+        assert!((db - -25.0).abs() < 1e-4, "{db}");
+
+        // The next bar at 120 BPM starts two seconds later
+        let db = duck_db_at(&DEFAULT_ENVELOPE, 120.0, 2.0, 4.0);
+        assert!((db - -25.0).abs() < 1e-4, "{db}");
+    }
+
+    #[test]
+    fn recovers_towards_unity() {
+        let curve: Vec<f32> = (0..8)
+            .map(|i| duck_db_at(&DEFAULT_ENVELOPE, 120.0, i as f64 * 0.25, 4.0))
+            .collect();
+
+        assert!(curve.windows(2).all(|pair| pair[1] > pair[0]), "{curve:?}");
+        assert!(curve[7] > -0.1, "{curve:?}");
+    }
+
+    #[test]
+    fn shapes_at_the_start_middle_and_end_of_the_cycle() {
+        for (shape, expected) in [
+            (EnvShape::Exponential, [-25.0, -4.2338e-4, -2.56e-6]),
+            (EnvShape::Linear, [-25.0, -12.5, 0.0]),
+            (EnvShape::Sine, [-25.0, 0.0, -25.0]),
+            (EnvShape::Triangle, [-25.0, 0.0, -25.0]),
+            (EnvShape::Logarithmic, [-25.0, -23.26725, 0.0]),
+        ] {
+            for (phase, expected) in [0.0, 0.5, 1.0].into_iter().zip(expected) {
+                let db = shape_gain(shape, phase, 4.0, 10.0, 10.0, 0.5, 50.0);
+                assert!(
+                    (db - expected).abs() < 1e-4,
+                    "{shape:?} at {phase}: {db} != {expected}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn zero_length_leaves_gain_unchanged() {
+        assert_eq!(duck_db_at(&DEFAULT_ENVELOPE, 120.0, 0.0, 0.0), 0.0);
+        assert_eq!(duck_db_at(&DEFAULT_ENVELOPE, 120.0, 1.3, 0.0), 0.0);
+    }
+
+    #[test]
+    fn pre_roll_wraps_into_previous_cycle() {
+        let beat = host_cycle_beat(120.0, -0.5, 4.0);
+        assert!((beat - 3.0).abs() < 1e-9, "{beat}");
+    }
+
+    #[test]
+    fn no_swing_leaves_the_phase_unchanged() {
+        for i in 0..40 {
+            let pair_phase = i as f32 * 0.05;
+            let phase = swing_phase(pair_phase, 0.0);
+            assert!(
+                (phase - pair_phase.fract()).abs() < 1e-6,
+                "{pair_phase}: {phase}"
+            );
+        }
+    }
+
+    #[test]
+    fn swing_delays_every_second_trough() {
+        // The first cycle's trough stays put
+        assert_eq!(swing_phase(0.0, 0.5), 0.0);
+        // The second cycle's trough moves from halfway through the pair to 1.5 cycles in
+        assert!(swing_phase(1.0, 0.5) > 0.5);
+        assert_eq!(swing_phase(1.5, 0.5), 0.0);
+        // And the second cycle is squeezed into what's left of the pair
+        assert!((swing_phase(1.75, 0.5) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn tempo_changes_keep_the_phase_continuous() {
+        let sample_duration = 1.0 / 48000.0;
+        let mut beat = 0.0;
+        let mut previous = beat;
+        for i in 0..96000 {
+            // The tempo jumps from 120 to 180 BPM after one second
+            let tempo = if i < 48000 { 120.0 } else { 180.0 };
+            beat = advance_beat(beat, tempo / 60.0, sample_duration, 4.0);
+
+            let step = (beat - previous).rem_euclid(4.0);
+            assert!(step > 0.0 && step < 1e-4, "{i}: {previous} -> {beat}");
+            previous = beat;
+        }
+
+        // Two beats at 120 BPM followed by three beats at 180 BPM
+        assert!((beat - 1.0).abs() < 1e-6, "{beat}");
+    }
+
+    #[test]
+    fn length_fade_keeps_the_gain_continuous() {
+        // The exponential shape recovers over beats, so only the other shapes depend on the length
+        let envelope = DuckEnvelope {
+            shape: EnvShape::Linear,
+            ..DEFAULT_ENVELOPE
+        };
+        let sample_duration = 1.0 / 48000.0;
+        let beats_per_second = 2.0;
+        let mut cycle_beats = 4.0;
+        let mut beat = 0.0;
+        let mut fade: Option<LengthFade> = None;
+        let mut previous_db = compute_duck_db(&envelope, 0.0, cycle_beats as f32);
+        for i in 0..90000 {
+            // The length doubles three quarters into the first cycle, where the new cycle is
+            // several decibels away from the old one
+            if i == 36000 {
+                fade = Some(LengthFade::new(beat, cycle_beats, 2400));
+                cycle_beats = 8.0;
+            }
+
+            let phase = (beat.rem_euclid(cycle_beats) / cycle_beats) as f32;
+            let mut db = compute_duck_db(&envelope, phase, cycle_beats as f32);
+            if let Some(fade) = &fade {
+                let cycle = fade.cycle(0.0);
+                let fading_db = compute_duck_db(&envelope, cycle.phase, cycle.cycle_beats);
+                db = cycle.blend(db, fading_db);
+            }
+
+            assert!(
+                (db - previous_db).abs() < 0.05,
+                "{i}: {previous_db} -> {db}"
+            );
+            previous_db = db;
+
+            beat = advance_beat(beat, beats_per_second, sample_duration, cycle_beats);
+            if fade
+                .as_mut()
+                .is_some_and(|fade| !fade.advance(beats_per_second, sample_duration))
+            {
+                fade = None;
+            }
+        }
+    }
+
+    #[test]
+    fn bars_follow_the_time_signature() {
+        let three_four = TimeSignature {
+            numerator: 3,
+            denominator: 4,
+        };
+        let six_eight = TimeSignature {
+            numerator: 6,
+            denominator: 8,
+        };
+
+        assert_eq!(Division::Bar.beats(TimeSignature::COMMON_TIME), 4.0);
+        assert_eq!(Division::Bar.beats(three_four), 3.0);
+        assert_eq!(Division::Bar.beats(six_eight), 3.0);
+        // Note values don't depend on the time signature
+        assert_eq!(Division::Whole.beats(three_four), 4.0);
+    }
+
+    #[test]
+    fn zero_hold_leaves_phase_unchanged() {
+        for phase in [0.0, 0.25, 0.5, 0.9, 1.0] {
+            assert_eq!(apply_hold(phase, 0.0), phase);
+        }
+
+        // Which makes the envelope the same as the bare shape
+        for shape in (0..EnvShape::variants().len()).map(EnvShape::from_index) {
+            let envelope = DuckEnvelope {
+                shape,
+                ..DEFAULT_ENVELOPE
+            };
+            for phase in [0.0, 0.1, 0.5, 0.75] {
+                assert_eq!(
+                    compute_duck_db(&envelope, phase, 4.0),
+                    shape_gain(shape, phase, 4.0, 10.0, 10.0, 0.5, 50.0),
+                    "{shape:?} at {phase}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn modifiers_scale_the_period() {
+        // A quarter note at 120 BPM lasts 24000 samples at 48 kHz
+        for (modifier, expected) in [
+            (Modifier::Straight, 24000),
+            (Modifier::Triplet, 16000),
+            (Modifier::Dotted, 36000),
+        ] {
+            let cycle_beats =
+                Division::Quarter.beats(TimeSignature::COMMON_TIME) * modifier.scale();
+            let samples = period_samples(120.0, cycle_beats, 48000.0);
+            assert!(
+                samples.abs_diff(expected) <= 1,
+                "{modifier:?}: {samples} != {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn tempo_multipliers_scale_the_period() {
+        // A bar at 120 BPM lasts 96000 samples at 48 kHz
+        for (tempo_mult, expected) in [
+            (TempoMult::Quarter, 384000),
+            (TempoMult::Half, 192000),
+            (TempoMult::Normal, 96000),
+            (TempoMult::Double, 48000),
+            (TempoMult::Quadruple, 24000),
+        ] {
+            let samples = period_samples(120.0 * tempo_mult.factor(), 4.0, 48000.0);
+            assert!(
+                samples.abs_diff(expected) <= 1,
+                "{tempo_mult:?}: {samples} != {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn reduction_never_exceeds_the_floor() {
+        for shape in (0..EnvShape::variants().len()).map(EnvShape::from_index) {
+            for (amount, release_pow) in [(0.5, 10.0), (1.0, 0.0), (1.0, 1.0), (1.0, 20.0)] {
+                let envelope = DuckEnvelope {
+                    shape,
+                    amount,
+                    release_pow,
+                    depth_db: 60.0,
+                    ..DEFAULT_ENVELOPE
+                };
+                for floor_db in [-60.0, -24.0, -6.0, 0.0] {
+                    for i in 0..100 {
+                        let gain_db = compute_duck_db(&envelope, i as f32 / 100.0, 4.0);
+                        let db = limit_duck_db(gain_db, floor_db, false);
+                        assert!(db >= floor_db, "{shape:?} at {floor_db} dB: {db}");
+                        if gain_db >= floor_db {
+                            assert_eq!(db, gain_db);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn centered_bias_leaves_phase_unchanged() {
+        for phase in [0.0, 0.25, 0.5, 0.9] {
+            assert_eq!(apply_bias(phase, 0.5), phase);
+        }
+    }
+
+    #[test]
+    fn custom_curve_interpolates_between_points() {
+        let curve = CustomCurve::default();
+        curve.set(&[1.0, 0.0]);
+
+        assert_eq!(curve.depth(0.0), 1.0);
+        assert!((curve.depth(0.25) - 0.5).abs() < 1e-6);
+        assert_eq!(curve.depth(0.5), 0.0);
+        // The second half of the cycle ramps back up towards the first point
+        assert!((curve.depth(0.75) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn custom_curve_replaces_shape() {
+        let curve = CustomCurve::default();
+        curve.set(&[0.5; CUSTOM_CURVE_POINTS]);
+        let envelope = DuckEnvelope {
+            custom_curve: Some(&curve),
+            ..DEFAULT_ENVELOPE
+        };
+
+        for phase in [0.0, 0.3, 0.8] {
+            let db = compute_duck_db(&envelope, phase, 4.0);
+            assert!((db - -12.5).abs() < 1e-4, "{db}");
+        }
+    }
+
+    #[test]
+    fn tremolo_is_symmetric_around_zero() {
+        for shape in [EnvShape::Sine, EnvShape::Triangle] {
+            assert!((tremolo_lfo(shape, 0.0) - 1.0).abs() < 1e-6);
+            assert!((tremolo_lfo(shape, 0.5) - -1.0).abs() < 1e-6);
+            assert!(tremolo_lfo(shape, 0.25).abs() < 1e-6);
+            assert!(tremolo_lfo(shape, 0.75).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use std::f32::consts::{FRAC_1_SQRT_2, PI};
+
+/// The DC blocker's cutoff frequency. Low enough to leave the audible range alone while still
+/// settling quickly.
+const DC_BLOCKER_CUTOFF_HZ: f32 = 5.0;
+
+/// A one-pole high-pass filter that removes DC offset from a single channel.
+pub struct DcBlocker {
+    coef: f32,
+    previous_input: f32,
+    previous_output: f32,
+}
+
+impl DcBlocker {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            coef: (-2.0 * PI * DC_BLOCKER_CUTOFF_HZ / sample_rate).exp(),
+            previous_input: 0.0,
+            previous_output: 0.0,
+        }
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        let output = input - self.previous_input + self.coef * self.previous_output;
+        self.previous_input = input;
+        self.previous_output = output;
+
+        output
+    }
+
+    /// Clears the filter's state so it doesn't ring out from before a reset.
+    pub fn reset(&mut self) {
+        self.previous_input = 0.0;
+        self.previous_output = 0.0;
+    }
+}
+
+/// A second order section in transposed direct form II.
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    /// A Butterworth lowpass or highpass at `frequency`, following the Audio EQ Cookbook.
+    fn butterworth(frequency: f32, sample_rate: f32, highpass: bool) -> Self {
+        let w0 = 2.0 * PI * frequency / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * FRAC_1_SQRT_2);
+        let a0 = 1.0 + alpha;
+        let (b0, b1) = if highpass {
+            ((1.0 + cos) / 2.0, -(1.0 + cos))
+        } else {
+            ((1.0 - cos) / 2.0, 1.0 - cos)
+        };
+
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b0 / a0,
+            a1: -2.0 * cos / a0,
+            a2: (1.0 - alpha) / a0,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    /// Replaces the coefficients while keeping the filter's state.
+    fn set_coefficients(&mut self, other: Biquad) {
+        *self = Biquad {
+            z1: self.z1,
+            z2: self.z2,
+            ..other
+        };
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let output = self.b0 * input + self.z1;
+        self.z1 = self.b1 * input - self.a1 * output + self.z2;
+        self.z2 = self.b2 * input - self.a2 * output;
+
+        output
+    }
+
+    fn reset(&mut self) {
+        self.z1 = 0.0;
+        self.z2 = 0.0;
+    }
+}
+
+/// A fourth order Linkwitz-Riley crossover that splits a single channel into a low and a high
+/// band. The two bands sum back up to the input with a flat magnitude response.
+pub struct Crossover {
+    frequency: f32,
+    low: [Biquad; 2],
+    high: [Biquad; 2],
+}
+
+impl Crossover {
+    pub fn new(frequency: f32, sample_rate: f32) -> Self {
+        let low = Biquad::butterworth(frequency, sample_rate, false);
+        let high = Biquad::butterworth(frequency, sample_rate, true);
+        Self {
+            frequency,
+            low: [low; 2],
+            high: [high; 2],
+        }
+    }
+
+    /// Moves the crossover point. This only recomputes the coefficients when the frequency has
+    /// actually changed. Frequencies close to Nyquist are clamped so the filters stay stable.
+    pub fn set_frequency(&mut self, frequency: f32, sample_rate: f32) {
+        if frequency == self.frequency {
+            return;
+        }
+
+        self.frequency = frequency;
+        let frequency = frequency.min(sample_rate * 0.45);
+        let low = Biquad::butterworth(frequency, sample_rate, false);
+        let high = Biquad::butterworth(frequency, sample_rate, true);
+        for filter in &mut self.low {
+            filter.set_coefficients(low);
+        }
+        for filter in &mut self.high {
+            filter.set_coefficients(high);
+        }
+    }
+
+    /// Splits `input` into its low and high bands.
+    pub fn split(&mut self, input: f32) -> (f32, f32) {
+        let low = self
+            .low
+            .iter_mut()
+            .fold(input, |x, filter| filter.process(x));
+        let high = self
+            .high
+            .iter_mut()
+            .fold(input, |x, filter| filter.process(x));
+
+        (low, high)
+    }
+
+    /// Clears the filters' state so they don't ring out from before a reset.
+    pub fn reset(&mut self) {
+        for filter in self.low.iter_mut().chain(&mut self.high) {
+            filter.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The RMS levels of the low and high bands after splitting a sine at `frequency`, skipping
+    /// the first second so the filters have settled.
+    fn band_levels(frequency: f32) -> (f32, f32) {
+        let sample_rate = 48000.0;
+        let mut crossover = Crossover::new(500.0, sample_rate);
+        let mut low_sum = 0.0;
+        let mut high_sum = 0.0;
+        for i in 0..96000 {
+            let input = (2.0 * PI * frequency * i as f32 / sample_rate).sin();
+            let (low, high) = crossover.split(input);
+            if i >= 48000 {
+                low_sum += low * low;
+                high_sum += high * high;
+            }
+        }
+
+        ((low_sum / 48000.0).sqrt(), (high_sum / 48000.0).sqrt())
+    }
+
+    #[test]
+    fn crossover_separates_bands() {
+        // A sine has an RMS level of 1/sqrt(2)
+        let (low, high) = band_levels(50.0);
+        assert!((low - FRAC_1_SQRT_2).abs() < 0.01, "{low}");
+        assert!(high < 0.001, "{high}");
+
+        let (low, high) = band_levels(5000.0);
+        assert!(low < 0.001, "{low}");
+        assert!((high - FRAC_1_SQRT_2).abs() < 0.01, "{high}");
+    }
+
+    #[test]
+    fn crossover_bands_sum_to_flat_magnitude() {
+        let sample_rate = 48000.0;
+        let mut crossover = Crossover::new(500.0, sample_rate);
+        let mut sum = 0.0;
+        for i in 0..96000 {
+            let input = (2.0 * PI * 500.0 * i as f32 / sample_rate).sin();
+            let (low, high) = crossover.split(input);
+            if i >= 48000 {
+                sum += (low + high) * (low + high);
+            }
+        }
+
+        let rms = (sum / 48000.0).sqrt();
+        assert!((rms - FRAC_1_SQRT_2).abs() < 0.01, "{rms}");
+    }
+
+    #[test]
+    fn dc_decays_to_zero() {
+        let mut blocker = DcBlocker::new(48000.0);
+        let first = blocker.process(1.0);
+        assert!((first - 1.0).abs() < 1e-6, "{first}");
+
+        let mut output = first;
+        for _ in 0..48000 {
+            output = blocker.process(1.0);
+        }
+        assert!(output.abs() < 1e-6, "{output}");
+    }
+
+    #[test]
+    fn reset_clears_state() {
+        let mut blocker = DcBlocker::new(48000.0);
+        for _ in 0..100 {
+            blocker.process(0.5);
+        }
+        blocker.reset();
+
+        assert_eq!(blocker.process(0.0), 0.0);
+    }
+}
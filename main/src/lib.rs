@@ -1,25 +1,643 @@
 // Forked and modified from: https://github.com/robbert-vdh/nih-plug/tree/master/plugins/examples/gain
 use nih_plug::prelude::*;
 use nih_plug_webview::*;
-use serde::Deserialize;
-use serde_json::json;
-use std::sync::atomic::{AtomicBool, Ordering};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use delay::DelayLine;
+use envelope::{
+    advance_beat, compute_duck_db, host_cycle_beat, limit_duck_db, swing_phase, tremolo_lfo,
+    Anchor, CustomCurve, Division, DuckEnvelope, EnvShape, EnvelopeTable, LengthFade, Modifier,
+    Quality, SyncMode, TempoMult, TimeSignature,
+};
+use filter::{Crossover, DcBlocker};
+use meter::{
+    decay_peak, AtomicF32, ChannelPeaks, Correlation, GainMatch, Meters, PeakHold, RmsWindow,
+    MAX_METER_CHANNELS,
+};
+use oversample::{Oversampler, Oversampling, OVERSAMPLING_LATENCY};
+use preset::{reset_to_defaults, set_many, Preset, FACTORY_PRESETS};
+use random::Rng;
+use scope::{SCOPE_DECIMATION, SCOPE_POINTS};
+use smoother::{DecimatedRamp, GainSmoother, ParamSmoother, SmoothStyle};
+use state::{migrate_state, STATE_VERSION};
+use tap::TapTempo;
+
+mod delay;
+mod envelope;
+mod filter;
+mod meter;
+mod oversample;
+mod preset;
+mod random;
+mod scope;
+mod smoother;
+mod state;
+mod tap;
+
+/// The attack time of the sidechain envelope follower.
+const SIDECHAIN_ATTACK_MS: f32 = 1.0;
+/// The release time of the sidechain envelope follower.
+const SIDECHAIN_RELEASE_MS: f32 = 150.0;
+/// The smallest change in gain reduction that is sent to the web UI's meter, in decibels.
+const METER_THRESHOLD_DB: f32 = 0.1;
+/// The time it takes for the level meters to decay by 12 dB after the signal stops.
+const PEAK_METER_DECAY_MS: f64 = 150.0;
+/// The editor's size before the user has resized it.
+const DEFAULT_EDITOR_SIZE: EditorSize = EditorSize {
+    width: 200,
+    height: 200,
+};
+/// The range `Action::SetUiScale` clamps the editor's scale to.
+const MIN_UI_SCALE: f32 = 0.5;
+const MAX_UI_SCALE: f32 = 3.0;
+/// The color behind the web view while the web UI loads, before the user has picked one.
+const DEFAULT_BACKGROUND_COLOR: BackgroundColor = BackgroundColor {
+    r: 150,
+    g: 150,
+    b: 150,
+    a: 255,
+};
+/// The limits the editor can be resized within until the web UI declares its own.
+const DEFAULT_SIZE_CONSTRAINTS: SizeConstraints = SizeConstraints {
+    min: EditorSize {
+        width: 200,
+        height: 200,
+    },
+    max: EditorSize {
+        width: 1200,
+        height: 1200,
+    },
+    aspect_ratio: None,
+};
+/// How long the stereo correlation meter's window is.
+const CORRELATION_WINDOW_MS: f32 = 300.0;
+/// The number of points in the envelope curve sent to the web UI, unless it requests otherwise.
+const DEFAULT_CURVE_POINTS: usize = 128;
+/// The maximum number of points the web UI can request for the envelope curve.
+const MAX_CURVE_POINTS: usize = 4096;
+/// The longest window the `rms_window_ms` parameter allows, used to size the RMS buffers.
+const MAX_RMS_WINDOW_MS: f32 = 1000.0;
+/// The longest lookahead the `lookahead_ms` parameter allows, used to size the delay lines.
+const MAX_LOOKAHEAD_MS: f32 = 20.0;
+/// How long the old and new cycles are crossfaded for when the cycle's length changes.
+const LENGTH_FADE_MS: f32 = 50.0;
+/// How long the gain match averages the ducking gain over. This is long enough to cover a few
+/// cycles, so the makeup gain doesn't pump along with the envelope.
+const GAIN_MATCH_WINDOW_MS: f32 = 2000.0;
+/// How long the output fades in for after the plugin is initialized or reset, so smoothers and
+/// filters settling from their initial state aren't heard as a click.
+const SOFT_START_MS: f32 = 20.0;
+/// How long changes to `amount`, `release_pow`, and `depth_db` take to ramp in, see
+/// [`SmoothStyle`].
+const ENVELOPE_SMOOTHING_MS: f32 = 50.0;
+/// The test tone's peak level, -12 dBFS. This leaves headroom for the output gain.
+const TEST_TONE_LEVEL: f32 = 0.25;
+/// The fraction of the safety limiter's ceiling above which the soft clipper starts engaging.
+const SOFT_CLIP_KNEE: f32 = 0.7;
+/// Setting this environment variable to any non-empty value enables the web view's developer tools
+/// in release builds.
+const DEVTOOLS_ENV_VAR: &str = "SOUT_EX_GAIN_DEVTOOLS";
+/// Setting this environment variable to the path of an HTML file makes the editor load its web UI
+/// from that file instead of the embedded `gui.html`, so the web UI can be changed without
+/// recompiling the plugin.
+const GUI_PATH_ENV_VAR: &str = "SOUT_EX_GAIN_GUI_PATH";
+/// The only links `Action::OpenUrl` opens. The web UI could be loaded from anywhere with
+/// `GUI_PATH_ENV_VAR`, so it can't be trusted to open arbitrary links.
+const ALLOWED_URLS: &[&str] = &[<SoutGainRs as Plugin>::URL];
+/// The minimum time between two `levels` messages sent to the web UI.
+const LEVELS_INTERVAL: Duration = Duration::from_millis(33);
+/// How often the transport display in the web UI is updated.
+const TRANSPORT_INTERVAL: Duration = Duration::from_millis(250);
 
 struct SoutGainRs {
     params: Arc<GainParams>,
+    /// The last tempo reported by the host. Reused when the host stops reporting a tempo.
     tempo: f64,
+    /// The last time signature reported by the host, or common time if it never reported one.
+    time_signature: TimeSignature,
+    sample_rate: f32,
+    /// The transport position in seconds advanced by the number of processed samples. This is
+    /// used in place of the host's position when the host doesn't report one.
+    fallback_seconds: f64,
+    /// The position within the cycle in beats for the modes that don't follow the host's
+    /// transport position, i.e. the free running, MIDI triggered, and retrigger on play modes. While
+    /// following the transport this tracks the host's position in beats. Hosts that only report
+    /// their position in seconds have this advanced by the tempo every sample instead, so the cycle
+    /// stays continuous when the tempo changes during playback.
+    accumulated_beat: f64,
+    /// The position within the second envelope's cycle in beats, advanced alongside
+    /// `accumulated_beat`.
+    second_beat: f64,
+    /// The position within a pair of cycles in beats, advanced alongside `accumulated_beat`. The
+    /// swing needs to know which cycle of the pair it's in.
+    pair_beat: f64,
+    /// Whether `accumulated_beat` is locked to the host's transport position. This is cleared when
+    /// the transport jumps or when another mode takes over, after which the next sample syncs back
+    /// up with the transport.
+    beat_synced: bool,
+    /// The cycle's length in beats during the previous sample, used to detect length changes.
+    previous_cycle_beats: f64,
+    /// The cycle from before the last length change while it's being crossfaded into the new one.
+    length_fade: Option<LengthFade>,
+    /// Whether the host's transport was playing during the previous block. Used to detect when
+    /// playback starts.
+    was_playing: bool,
+    /// The sidechain envelope followers' current values for each channel, as linear peak levels.
+    /// Allocated in `initialize()`.
+    sidechain_envelopes: Vec<f32>,
+    /// The gate's level detectors for each channel, as linear peak levels. These use the same
+    /// attack and release times as the sidechain followers. Allocated in `initialize()`.
+    gate_envelopes: Vec<f32>,
+    /// One-pole filter coefficients for the sidechain envelope follower, computed in
+    /// `initialize()` from `SIDECHAIN_ATTACK_MS` and `SIDECHAIN_RELEASE_MS`.
+    sidechain_attack_coef: f32,
+    sidechain_release_coef: f32,
+    /// Metering data shared with the editor.
+    meters: Arc<Meters>,
+    /// The weight the peak meters' previous values are multiplied by for every sample, computed
+    /// in `initialize()` from `PEAK_METER_DECAY_MS`.
+    peak_meter_decay_weight: f32,
+    /// The one-pole smoothed ducking gain for each channel, so the envelope stays continuous
+    /// across blocks. Allocated in `initialize()`.
+    duck_gain_state: Vec<f32>,
+    /// Interpolates each channel's ducking gain between evaluations of the envelope at the lower
+    /// `quality` settings. Allocated in `initialize()`.
+    duck_ramps: Vec<DecimatedRamp>,
+    /// The envelope's shape across a cycle, recomputed at the start of a block whenever the
+    /// envelope's settings change.
+    envelope_table: EnvelopeTable,
+    /// The same as `envelope_table`, but for the second envelope.
+    second_envelope_table: EnvelopeTable,
+    /// The oversampling filters for each channel. Allocated in `initialize()`.
+    oversamplers: Vec<Oversampler>,
+    /// The oversampling setting during the previous block, used to detect changes.
+    oversampling: Oversampling,
+    /// Delays each channel by the lookahead time. Allocated in `initialize()` for
+    /// `MAX_LOOKAHEAD_MS`.
+    lookahead_delays: Vec<DelayLine>,
+    /// Removes DC offset from each output channel. Allocated in `initialize()`.
+    dc_blockers: Vec<DcBlocker>,
+    /// Splits each channel into a low and a high band when `band_split` is enabled. Allocated in
+    /// `initialize()`.
+    crossovers: Vec<Crossover>,
+    /// Delays each channel's high band by the oversampling latency, since only the low band goes
+    /// through the oversampled gain stage. Allocated in `initialize()`.
+    band_delays: Vec<DelayLine>,
+    /// Averages each channel's ducking gain for `gain_match`. Allocated in `initialize()`.
+    gain_matches: Vec<GainMatch>,
+    /// The last value sent as the gain reduction MIDI CC, if any has been sent since the last
+    /// reset.
+    last_cc_value: Option<f32>,
+    /// The length of the fade-in after a reset in samples, see [`SOFT_START_MS`].
+    soft_start_samples: usize,
+    /// How many samples of the fade-in are still left. This is zero once the fade-in has
+    /// finished.
+    soft_start_remaining: usize,
+    /// The test tone's phase, in cycles.
+    tone_phase: f64,
+    /// Smooths the `gain` parameter over `smoothing_ms`.
+    gain_smoother: GainSmoother,
+    /// Smooth `amount`, `release_pow`, and `depth_db` in the style set by `smooth_style`.
+    amount_smoother: ParamSmoother,
+    release_pow_smoother: ParamSmoother,
+    depth_db_smoother: ParamSmoother,
+    /// Smooths the amount set through the `amount_cc` MIDI CC.
+    cc_amount: Smoother<f32>,
+    /// The `amount` parameter's value when the CC took over. The CC controls the amount until
+    /// the parameter changes from this value.
+    cc_amount_base: Option<f32>,
+    /// The latency last reported to the host, in samples.
+    latency_samples: u32,
+    /// Whether the layout has a mono input and more than one output channel, in which case the
+    /// input is copied to the other output channels before processing.
+    upmix_mono: bool,
+    /// The output scope frame that is currently being filled, along with the point and the
+    /// sample within that point the next sample goes to.
+    scope_frame: [f32; SCOPE_POINTS],
+    scope_point: usize,
+    scope_sample: usize,
+    /// The RMS level meters' windows. Allocated in `initialize()` for `MAX_RMS_WINDOW_MS`.
+    input_rms: RmsWindow,
+    output_rms: RmsWindow,
+    /// The output's stereo correlation. Allocated in `initialize()` for `CORRELATION_WINDOW_MS`.
+    correlation: Correlation,
+    /// The peak hold markers for the input and output.
+    input_hold: PeakHold,
+    output_hold: PeakHold,
+    /// The A and B snapshots for comparing two settings, stored as normalized values. These only
+    /// live in memory and are not saved with the project.
+    snapshots: Arc<Mutex<[Option<Preset>; 2]>>,
 }
 
 #[derive(Deserialize)]
 #[serde(tag = "type")]
 enum Action {
     Init,
-    SetSize { width: u32, height: u32 },
-    SetGain { value: f32 },
-    SetLength { value: f32 },
-    SetPow { value: f32 },
-    SetAmount { value: f32 },
+    SetSize {
+        width: u32,
+        height: u32,
+    },
+    SetSizeConstraints(SizeLimits),
+    SetBackgroundColor {
+        r: u8,
+        g: u8,
+        b: u8,
+        a: u8,
+    },
+    SetUiScale {
+        scale: f32,
+    },
+    OpenUrl {
+        url: String,
+    },
+    SetAspectLock {
+        ratio: f32,
+    },
+    SetGain {
+        value: f32,
+    },
+    SetSmoothing {
+        value: f32,
+    },
+    SetSmoothStyle {
+        value: u8,
+    },
+    SetLength {
+        value: f32,
+    },
+    SetReleasePow {
+        value: f32,
+    },
+    SetAmount {
+        value: f32,
+    },
+    SetSecondEnv {
+        value: bool,
+    },
+    SetLength2 {
+        value: f32,
+    },
+    SetPow2 {
+        value: f32,
+    },
+    SetAmount2 {
+        value: f32,
+    },
+    SetSyncMode {
+        value: u8,
+    },
+    SetAnchor {
+        value: u8,
+    },
+    SetRateHz {
+        hz: f32,
+    },
+    SetPeriodMs {
+        value: f32,
+    },
+    SetMix {
+        value: f32,
+    },
+    SetOutputGain {
+        value: f32,
+    },
+    SetInputGain {
+        value: f32,
+    },
+    SetWidth {
+        value: f32,
+    },
+    SetSidechain {
+        value: bool,
+    },
+    SetMidiTrigger {
+        value: bool,
+    },
+    SetMidiAmount {
+        value: bool,
+    },
+    SetAmountCc {
+        value: u8,
+    },
+    SetShape {
+        value: u8,
+    },
+    SetPhase {
+        value: f32,
+    },
+    SetSwing {
+        value: f32,
+    },
+    SetDivision {
+        value: u8,
+    },
+    SetModifier {
+        value: u8,
+    },
+    SetRetriggerOnPlay {
+        value: bool,
+    },
+    SetFreezeWhenStopped {
+        value: bool,
+    },
+    SetSmoothLengthChange {
+        value: bool,
+    },
+    SetStereoOffset {
+        value: f32,
+    },
+    SetBypass {
+        value: bool,
+    },
+    SetAttack {
+        value: f32,
+    },
+    SetRelease {
+        value: f32,
+    },
+    SetInvert {
+        value: bool,
+    },
+    SetPolarity {
+        value: bool,
+    },
+    SetDepth {
+        value: f32,
+    },
+    SetHold {
+        value: f32,
+    },
+    SetOversampling {
+        value: u8,
+    },
+    SetQuality {
+        value: u8,
+    },
+    SetLookahead {
+        value: f32,
+    },
+    SetSafetyLimit {
+        value: bool,
+    },
+    SetCeiling {
+        value: f32,
+    },
+    SetDcFilter {
+        value: bool,
+    },
+    SetBandSplit {
+        value: bool,
+    },
+    SetGainMatch {
+        value: bool,
+    },
+    SetCcOutput {
+        value: bool,
+    },
+    SetCcNumber {
+        value: u8,
+    },
+    SetCrossover {
+        value: f32,
+    },
+    SetBandAmounts {
+        low: f32,
+        high: f32,
+    },
+    SetFloor {
+        value: f32,
+    },
+    SetAttackPow {
+        value: f32,
+    },
+    SetRmsWindow {
+        value: f32,
+    },
+    SetGainDb {
+        db: f32,
+    },
+    SetReleasePowValue {
+        value: f32,
+    },
+    SetAmountValue {
+        value: f32,
+    },
+    SetIntensity {
+        value: f32,
+    },
+    SetListen {
+        value: u8,
+    },
+    SetTestTone {
+        value: bool,
+    },
+    SetToneHz {
+        value: f32,
+    },
+    SetStereoLink {
+        value: bool,
+    },
+    SetEmitKey {
+        value: bool,
+    },
+    /// Request the envelope curve. The curve is sent again with the same number of points
+    /// whenever a parameter affecting its shape changes.
+    RequestCurve {
+        points: Option<usize>,
+    },
+    SavePreset {
+        path: String,
+    },
+    LoadFactoryPreset {
+        index: usize,
+    },
+    StoreSnapshot {
+        slot: char,
+    },
+    RecallSnapshot {
+        slot: char,
+    },
+    CopyAtoB,
+    Tap,
+    SetTempoMult {
+        value: u8,
+    },
+    SetBias {
+        value: f32,
+    },
+    SetCustom {
+        value: bool,
+    },
+    SetCustomCurve {
+        points: Vec<f32>,
+    },
+    Randomize {
+        seed: Option<u64>,
+    },
+    ResetDefaults,
+    ToggleBypass,
+    ClearClip,
+    RequestParamMeta {
+        id: String,
+    },
+    SetMany {
+        values: Vec<(String, f32)>,
+    },
+    SetPeakHold {
+        value: f32,
+    },
+    SetMode {
+        value: u8,
+    },
+    SetThreshold {
+        value: f32,
+    },
+    SetTremoloDepth {
+        value: f32,
+    },
+    SetTremoloShape {
+        value: u8,
+    },
+    RequestState,
+}
+
+/// How the plugin modulates the signal's gain.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// The tempo synced or sidechain triggered ducking envelope.
+    #[id = "duck"]
+    #[name = "Duck"]
+    Duck,
+    /// Mutes the signal while it's below `threshold_db`.
+    #[id = "gate"]
+    #[name = "Gate"]
+    Gate,
+    /// A continuous LFO following the tempo synced cycle, boosting and cutting the gain by the
+    /// same amount.
+    #[id = "tremolo"]
+    #[name = "Tremolo"]
+    Tremolo,
+}
+
+/// What the plugin outputs. The modes other than `Normal` are meant for monitoring while dialing
+/// in the duck.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Listen {
+    #[id = "normal"]
+    #[name = "Normal"]
+    Normal,
+    /// Only the ducked signal, regardless of the dry/wet mix.
+    #[id = "wet_only"]
+    #[name = "Wet Only"]
+    WetOnly,
+    /// The sidechain input the follower listens to.
+    #[id = "sidechain_only"]
+    #[name = "Sidechain Only"]
+    SidechainOnly,
+}
+
+/// The editor's window size, persisted so it survives saving and reloading a project.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct EditorSize {
+    width: u32,
+    height: u32,
+}
+
+impl EditorSize {
+    /// Multiplies both dimensions by `scale`, rounded to whole pixels.
+    fn scaled(self, scale: f32) -> EditorSize {
+        EditorSize {
+            width: (self.width as f32 * scale).round() as u32,
+            height: (self.height as f32 * scale).round() as u32,
+        }
+    }
+}
+
+/// The color shown behind the web view while the web UI loads, persisted along with the editor's
+/// size.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct BackgroundColor {
+    r: u8,
+    g: u8,
+    b: u8,
+    a: u8,
+}
+
+/// The size limits the web UI declares with `Action::SetSizeConstraints`.
+#[derive(Deserialize)]
+struct SizeLimits {
+    min_w: u32,
+    min_h: u32,
+    max_w: u32,
+    max_h: u32,
+}
+
+/// The smallest and largest size the editor can be resized to, and the aspect ratio it's locked
+/// to, if any.
+#[derive(Debug, Clone, Copy)]
+struct SizeConstraints {
+    min: EditorSize,
+    max: EditorSize,
+    /// The width divided by the height.
+    aspect_ratio: Option<f32>,
+}
+
+impl SizeConstraints {
+    /// Replaces the minimum and maximum size. Zero sized minimums are raised to a single pixel,
+    /// and maximums below the minimum are raised to the minimum.
+    fn set_limits(&mut self, limits: SizeLimits) {
+        self.min = EditorSize {
+            width: limits.min_w.max(1),
+            height: limits.min_h.max(1),
+        };
+        self.max = EditorSize {
+            width: limits.max_w.max(self.min.width),
+            height: limits.max_h.max(self.min.height),
+        };
+    }
+
+    /// Locks the aspect ratio to `ratio`. Ratios that aren't positive unlock it again.
+    fn set_aspect_ratio(&mut self, ratio: f32) {
+        self.aspect_ratio = (ratio.is_finite() && ratio > 0.0).then_some(ratio);
+    }
+
+    /// Fits `size` to the constraints. With a locked aspect ratio the height follows the width,
+    /// unless that would put the height out of bounds, in which case the width follows the
+    /// clamped height instead.
+    fn apply(&self, size: EditorSize) -> EditorSize {
+        let Some(ratio) = self.aspect_ratio else {
+            return self.clamp(size);
+        };
+
+        let locked = EditorSize {
+            width: size.width,
+            height: (size.width as f32 / ratio).round() as u32,
+        };
+        let clamped = self.clamp(locked);
+        if clamped.height == locked.height {
+            clamped
+        } else {
+            self.clamp(EditorSize {
+                width: (clamped.height as f32 * ratio).round() as u32,
+                height: clamped.height,
+            })
+        }
+    }
+
+    fn clamp(&self, size: EditorSize) -> EditorSize {
+        EditorSize {
+            width: size.width.clamp(self.min.width, self.max.width),
+            height: size.height.clamp(self.min.height, self.max.height),
+        }
+    }
 }
 
 #[derive(Params)]
@@ -28,14 +646,374 @@ struct GainParams {
     pub gain: FloatParam,
     gain_value_changed: Arc<AtomicBool>,
 
+    /// How long changes to `gain` take to ramp in. The gain is smoothed in `process()` rather
+    /// than by the parameter itself, since nih-plug's smoothing times can't change at runtime.
+    #[id = "smoothing"]
+    pub smoothing_ms: FloatParam,
+    smoothing_ms_value_changed: Arc<AtomicBool>,
+
+    /// How `amount`, `release_pow`, and `depth_db` ramp towards new values. Like `gain`, these are
+    /// smoothed in `process()` so the style can change at runtime.
+    #[id = "smooth_style"]
+    pub smooth_style: EnumParam<SmoothStyle>,
+    smooth_style_value_changed: Arc<AtomicBool>,
+
     #[id = "lenght"]
     pub length: IntParam,
+    length_value_changed: Arc<AtomicBool>,
 
+    /// The steepness of the recovery out of the duck.
     #[id = "pump"]
-    pub pow: FloatParam,
+    pub release_pow: FloatParam,
+    release_pow_value_changed: Arc<AtomicBool>,
 
     #[id = "amount"]
     pub amount: FloatParam,
+    amount_value_changed: Arc<AtomicBool>,
+
+    /// Layer a second envelope with its own length on top of the first one, for polyrhythmic
+    /// pumping. It shares the first envelope's shape, division, and depth.
+    #[id = "second_env"]
+    pub second_env: BoolParam,
+    second_env_value_changed: Arc<AtomicBool>,
+
+    /// The second envelope's length, in steps of the same division as `length`.
+    #[id = "length2"]
+    pub length2: IntParam,
+    length2_value_changed: Arc<AtomicBool>,
+
+    /// The second envelope's `release_pow`.
+    #[id = "pow2"]
+    pub release_pow2: FloatParam,
+    release_pow2_value_changed: Arc<AtomicBool>,
+
+    /// The second envelope's `amount`.
+    #[id = "amount2"]
+    pub amount2: FloatParam,
+    amount2_value_changed: Arc<AtomicBool>,
+
+    /// Whether the cycle follows the host's tempo or runs at a fixed rate or period.
+    #[id = "sync_mode"]
+    pub sync_mode: EnumParam<SyncMode>,
+    sync_mode_value_changed: Arc<AtomicBool>,
+
+    /// Where the tempo synced cycle starts from. `retrigger_on_play` takes precedence over the
+    /// song start.
+    #[id = "anchor"]
+    pub anchor: EnumParam<Anchor>,
+    anchor_value_changed: Arc<AtomicBool>,
+
+    /// How many cycles per second to run in the `FreeHz` sync mode.
+    #[id = "rate"]
+    pub rate_hz: FloatParam,
+    rate_hz_value_changed: Arc<AtomicBool>,
+
+    /// How long a single cycle lasts in the `FreeMs` sync mode.
+    #[id = "period"]
+    pub period_ms: FloatParam,
+    period_ms_value_changed: Arc<AtomicBool>,
+
+    #[id = "mix"]
+    pub mix: FloatParam,
+    mix_value_changed: Arc<AtomicBool>,
+
+    #[id = "out"]
+    pub output_gain: FloatParam,
+    output_gain_value_changed: Arc<AtomicBool>,
+
+    /// Trims the input before anything else, so the plugin behaves the same regardless of the
+    /// level coming into it.
+    #[id = "in"]
+    pub input_gain: FloatParam,
+    input_gain_value_changed: Arc<AtomicBool>,
+
+    /// The stereo width applied to the output, from mono at 0 to doubling the side signal at 2.
+    #[id = "width"]
+    pub width: FloatParam,
+    width_value_changed: Arc<AtomicBool>,
+
+    #[id = "sidechain"]
+    pub sidechain: BoolParam,
+    sidechain_value_changed: Arc<AtomicBool>,
+
+    #[id = "midi_trigger"]
+    pub midi_trigger: BoolParam,
+    midi_trigger_value_changed: Arc<AtomicBool>,
+
+    /// Let the `amount_cc` MIDI CC control `amount`. Changing `amount` from the host or the web
+    /// UI takes over from the CC again.
+    #[id = "midi_amount"]
+    pub midi_amount: BoolParam,
+    midi_amount_value_changed: Arc<AtomicBool>,
+
+    #[id = "amount_cc"]
+    pub amount_cc: IntParam,
+    amount_cc_value_changed: Arc<AtomicBool>,
+
+    #[id = "shape"]
+    pub shape: EnumParam<EnvShape>,
+    shape_value_changed: Arc<AtomicBool>,
+
+    #[id = "phase"]
+    pub phase_offset: FloatParam,
+    phase_offset_value_changed: Arc<AtomicBool>,
+
+    /// Delays every second cycle's trough for a swung groove. This only applies while following
+    /// the host's tempo.
+    #[id = "swing"]
+    pub swing: FloatParam,
+    swing_value_changed: Arc<AtomicBool>,
+
+    /// The note value of a single `length` step. This defaults to a quarter note, which matches
+    /// the behavior from before this parameter existed, so older saved states don't need to be
+    /// migrated.
+    #[id = "division"]
+    pub division: EnumParam<Division>,
+    division_value_changed: Arc<AtomicBool>,
+
+    #[id = "mod"]
+    pub modifier: EnumParam<Modifier>,
+    modifier_value_changed: Arc<AtomicBool>,
+
+    /// Restart the cycle when the host starts playing instead of following the absolute
+    /// transport position.
+    #[id = "retrigger_on_play"]
+    pub retrigger_on_play: BoolParam,
+    retrigger_on_play_value_changed: Arc<AtomicBool>,
+
+    /// Return to unity gain while the host's transport is stopped.
+    #[id = "freeze_when_stopped"]
+    pub freeze_when_stopped: BoolParam,
+    freeze_when_stopped_value_changed: Arc<AtomicBool>,
+
+    /// Crossfade between the old and the new cycle when the cycle's length changes, instead of
+    /// jumping straight to the new cycle's position.
+    #[id = "smooth_length_change"]
+    pub smooth_length_change: BoolParam,
+    smooth_length_change_value_changed: Arc<AtomicBool>,
+
+    #[id = "stereo_offset"]
+    pub stereo_offset: FloatParam,
+    stereo_offset_value_changed: Arc<AtomicBool>,
+
+    #[id = "bypass"]
+    pub bypass: BoolParam,
+    bypass_value_changed: Arc<AtomicBool>,
+
+    /// How quickly the ducking gain moves down into the duck.
+    #[id = "attack"]
+    pub attack_ms: FloatParam,
+    attack_ms_value_changed: Arc<AtomicBool>,
+
+    /// How quickly the ducking gain recovers from the duck.
+    #[id = "release"]
+    pub release_ms: FloatParam,
+    release_ms_value_changed: Arc<AtomicBool>,
+
+    /// Boost the signal on the beat instead of ducking it.
+    #[id = "invert"]
+    pub invert: BoolParam,
+    invert_value_changed: Arc<AtomicBool>,
+
+    /// Flip the polarity of the signal before any other processing.
+    #[id = "polarity"]
+    pub polarity: BoolParam,
+    polarity_value_changed: Arc<AtomicBool>,
+
+    /// The deepest gain reduction the envelope can reach when `amount` is fully up.
+    #[id = "depth"]
+    pub depth_db: FloatParam,
+    depth_db_value_changed: Arc<AtomicBool>,
+
+    /// The fraction of the cycle the envelope stays at its deepest point before recovering.
+    #[id = "hold"]
+    pub hold: FloatParam,
+    hold_value_changed: Arc<AtomicBool>,
+
+    /// Oversample the ducking gain stage to reduce aliasing from fast, deep modulation. This adds
+    /// latency.
+    #[id = "oversample"]
+    pub oversampling: EnumParam<Oversampling>,
+    oversampling_value_changed: Arc<AtomicBool>,
+
+    /// Trade the ducking envelope's accuracy for CPU time, see [`Quality`].
+    #[id = "quality"]
+    pub quality: EnumParam<Quality>,
+    quality_value_changed: Arc<AtomicBool>,
+
+    /// Delays the signal so the ducking envelope, and the sidechain follower in particular, can
+    /// react before the audio arrives. This adds latency.
+    #[id = "lookahead"]
+    pub lookahead_ms: FloatParam,
+    lookahead_ms_value_changed: Arc<AtomicBool>,
+
+    /// Soft clip the output so it never goes above `ceiling_db`.
+    #[id = "safety_limit"]
+    pub safety_limit: BoolParam,
+    safety_limit_value_changed: Arc<AtomicBool>,
+
+    /// The highest level the safety limiter lets through.
+    #[id = "ceiling"]
+    pub ceiling_db: FloatParam,
+    ceiling_db_value_changed: Arc<AtomicBool>,
+
+    /// Remove any DC offset from the output with a gentle high-pass filter.
+    #[id = "dc_filter"]
+    pub dc_filter: BoolParam,
+    dc_filter_value_changed: Arc<AtomicBool>,
+
+    /// Split the signal into a low and a high band and duck each band by its own amount.
+    #[id = "band_split"]
+    pub band_split: BoolParam,
+    band_split_value_changed: Arc<AtomicBool>,
+
+    /// Where the signal is split into the low and high bands.
+    #[id = "crossover"]
+    pub crossover_hz: FloatParam,
+    crossover_hz_value_changed: Arc<AtomicBool>,
+
+    /// How much of the ducking envelope applies to the low band when the bands are split.
+    #[id = "low_amount"]
+    pub low_amount: FloatParam,
+    low_amount_value_changed: Arc<AtomicBool>,
+
+    /// How much of the ducking envelope applies to the high band when the bands are split.
+    #[id = "high_amount"]
+    pub high_amount: FloatParam,
+    high_amount_value_changed: Arc<AtomicBool>,
+
+    /// Apply makeup gain that compensates for the average gain reduction, so the level stays the
+    /// same as when bypassed.
+    #[id = "gain_match"]
+    pub gain_match: BoolParam,
+    gain_match_value_changed: Arc<AtomicBool>,
+
+    /// Send the gain reduction as a MIDI CC, so it can drive other plugins or external gear.
+    #[id = "cc_output"]
+    pub cc_output: BoolParam,
+    cc_output_value_changed: Arc<AtomicBool>,
+
+    #[id = "cc_number"]
+    pub cc_number: IntParam,
+    cc_number_value_changed: Arc<AtomicBool>,
+
+    /// The lowest the ducking envelope can pull the gain down to, so the signal never disappears
+    /// completely.
+    #[id = "floor"]
+    pub floor_db: FloatParam,
+    floor_db_value_changed: Arc<AtomicBool>,
+
+    /// The steepness of the descent into the duck for the sine and triangle shapes. The other
+    /// shapes jump straight back into the duck at the end of the cycle, so they have no descent
+    /// for this to shape and the web UI hides it for them.
+    #[id = "attack_pow"]
+    pub attack_pow: FloatParam,
+    attack_pow_value_changed: Arc<AtomicBool>,
+
+    /// The length of the window the level meters average over.
+    #[id = "rms_window"]
+    pub rms_window_ms: FloatParam,
+    rms_window_ms_value_changed: Arc<AtomicBool>,
+
+    /// How long the peak hold markers stay at a peak before falling back down.
+    #[id = "peak_hold"]
+    pub peak_hold_ms: FloatParam,
+    peak_hold_ms_value_changed: Arc<AtomicBool>,
+
+    #[id = "mode"]
+    pub mode: EnumParam<Mode>,
+    mode_value_changed: Arc<AtomicBool>,
+
+    /// The level below which the gate mutes the signal.
+    #[id = "threshold"]
+    pub threshold_db: FloatParam,
+    threshold_db_value_changed: Arc<AtomicBool>,
+
+    /// How far the tremolo boosts and cuts the gain at the LFO's peaks.
+    #[id = "tremolo_depth"]
+    pub tremolo_depth_db: FloatParam,
+    tremolo_depth_db_value_changed: Arc<AtomicBool>,
+
+    #[id = "tremolo_shape"]
+    pub tremolo_shape: EnumParam<EnvShape>,
+    tremolo_shape_value_changed: Arc<AtomicBool>,
+
+    /// A macro that sets `amount`, `release_pow`, and `depth_db` together when changed from the
+    /// web UI. The macro only moves the other parameters, so they can still be adjusted
+    /// individually afterwards.
+    #[id = "intensity"]
+    pub intensity: FloatParam,
+    intensity_value_changed: Arc<AtomicBool>,
+
+    /// Monitor only part of the signal. This is excluded from presets.
+    #[id = "listen"]
+    pub listen: EnumParam<Listen>,
+    listen_value_changed: Arc<AtomicBool>,
+
+    /// Replace the input with a sine wave, to audition the envelope without a source. This is
+    /// excluded from presets.
+    #[id = "test_tone"]
+    pub test_tone: BoolParam,
+    test_tone_value_changed: Arc<AtomicBool>,
+
+    #[id = "tone_hz"]
+    pub tone_hz: FloatParam,
+    tone_hz_value_changed: Arc<AtomicBool>,
+
+    /// Have every channel's sidechain follower follow the loudest sidechain channel, instead of
+    /// each channel following its own sidechain channel.
+    #[id = "stereo_link"]
+    pub stereo_link: BoolParam,
+    stereo_link_value_changed: Arc<AtomicBool>,
+
+    /// Write the ducking envelope to the `Key` auxiliary output, so it can be routed to other
+    /// plugins' sidechain inputs. The key is one minus each channel's ducking gain, so it's silent
+    /// at unity gain and reaches one when fully ducked.
+    #[id = "emit_key"]
+    pub emit_key: BoolParam,
+    emit_key_value_changed: Arc<AtomicBool>,
+
+    /// Scales the host's tempo for the tempo synced and MIDI triggered modes.
+    #[id = "tempo_mult"]
+    pub tempo_mult: EnumParam<TempoMult>,
+    tempo_mult_value_changed: Arc<AtomicBool>,
+
+    /// Where the midpoint of the envelope's shape sits within the cycle.
+    #[id = "bias"]
+    pub bias: FloatParam,
+    bias_value_changed: Arc<AtomicBool>,
+
+    /// Use the curve drawn in the web UI instead of `shape`.
+    #[id = "custom"]
+    pub custom: BoolParam,
+    custom_value_changed: Arc<AtomicBool>,
+
+    /// The custom curve's points, persisted so the drawn curve is saved with the project. The
+    /// audio thread reads `custom_curve_table` instead, which is kept in sync with this.
+    #[persist = "custom-curve"]
+    custom_curve: Arc<RwLock<Vec<f32>>>,
+    custom_curve_table: CustomCurve,
+
+    /// The editor's size before `ui_scale` is applied. The web UI, the size constraints, and the
+    /// `set_size` messages all work with this unscaled size.
+    #[persist = "editor-size"]
+    editor_size: Arc<RwLock<EditorSize>>,
+
+    /// How much larger the editor's window is than `editor_size`. The web UI zooms its contents
+    /// by the same factor. The web view already applies the system's DPI scaling to both the
+    /// window and its contents, so this is only an extra zoom on top of that for high resolution
+    /// screens where the system scaling isn't enough.
+    #[persist = "ui-scale"]
+    ui_scale: Arc<RwLock<f32>>,
+
+    /// The web view's background color is set when the editor is created, so changing this only
+    /// takes effect the next time the editor is opened.
+    #[persist = "bg-color"]
+    bg_color: Arc<RwLock<BackgroundColor>>,
+
+    /// The format version of the saved state, used by `migrate_state()`.
+    #[persist = "state-version"]
+    state_version: Arc<RwLock<u32>>,
 }
 
 impl Default for SoutGainRs {
@@ -43,222 +1021,3697 @@ impl Default for SoutGainRs {
         Self {
             params: Arc::new(GainParams::default()),
             tempo: 120.0,
+            time_signature: TimeSignature::COMMON_TIME,
+            sample_rate: 44100.0,
+            fallback_seconds: 0.0,
+            accumulated_beat: 0.0,
+            second_beat: 0.0,
+            pair_beat: 0.0,
+            beat_synced: false,
+            previous_cycle_beats: 0.0,
+            length_fade: None,
+            was_playing: false,
+            sidechain_envelopes: Vec::new(),
+            gate_envelopes: Vec::new(),
+            sidechain_attack_coef: one_pole_coefficient(SIDECHAIN_ATTACK_MS, 44100.0),
+            sidechain_release_coef: one_pole_coefficient(SIDECHAIN_RELEASE_MS, 44100.0),
+            meters: Arc::new(Meters::default()),
+            peak_meter_decay_weight: 1.0,
+            duck_gain_state: Vec::new(),
+            duck_ramps: Vec::new(),
+            envelope_table: EnvelopeTable::default(),
+            second_envelope_table: EnvelopeTable::default(),
+            oversamplers: Vec::new(),
+            oversampling: Oversampling::Off,
+            lookahead_delays: Vec::new(),
+            dc_blockers: Vec::new(),
+            crossovers: Vec::new(),
+            band_delays: Vec::new(),
+            gain_matches: Vec::new(),
+            last_cc_value: None,
+            soft_start_samples: 0,
+            soft_start_remaining: 0,
+            tone_phase: 0.0,
+            gain_smoother: GainSmoother::new(1.0),
+            amount_smoother: ParamSmoother::new(0.5),
+            release_pow_smoother: ParamSmoother::new(10.0),
+            depth_db_smoother: ParamSmoother::new(50.0),
+            cc_amount: Smoother::new(SmoothingStyle::Linear(50.0)),
+            cc_amount_base: None,
+            latency_samples: 0,
+            upmix_mono: false,
+            scope_frame: [0.0; SCOPE_POINTS],
+            scope_point: 0,
+            scope_sample: 0,
+            input_rms: RmsWindow::new(0),
+            output_rms: RmsWindow::new(0),
+            correlation: Correlation::new(0),
+            input_hold: PeakHold::default(),
+            output_hold: PeakHold::default(),
+            snapshots: Arc::new(Mutex::new([None, None])),
         }
     }
 }
 
-impl Default for GainParams {
-    fn default() -> Self {
-        let gain_value_changed = Arc::new(AtomicBool::new(false));
-
-        let v = gain_value_changed.clone();
-        let param_callback = Arc::new(move |_: f32| {
-            v.store(true, Ordering::Relaxed);
-        });
-
-        Self {
-            gain: FloatParam::new(
-                "Gain",
-                util::db_to_gain(0.0),
-                FloatRange::Skewed {
-                    min: util::db_to_gain(-30.0),
-                    max: util::db_to_gain(30.0),
-                    factor: FloatRange::gain_skew_factor(-30.0, 30.0),
-                },
-            )
-            .with_smoother(SmoothingStyle::Logarithmic(50.0))
-            .with_unit(" dB")
-            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
-            .with_string_to_value(formatters::s2v_f32_gain_to_db())
-            .with_callback(param_callback.clone()),
-            gain_value_changed,
-
-            pow: FloatParam::new(
-                "Pow",
-                10.0,
-                FloatRange::Linear {
-                    min: 0.0,
-                    max: 20.0,
-                },
-            ),
-
-            length: IntParam::new("Lenght", 0, IntRange::Linear { min: 0, max: 4 })
-                .with_unit(" bar"),
+/// Where a block starts on the host's timeline, as read from the transport in `process()`.
+#[derive(Debug, Clone, Copy)]
+struct BlockPosition {
+    /// The position in seconds, or where the previous block ended if the host doesn't report it.
+    seconds: f64,
+    /// The position in quarter notes scaled by the tempo multiplier, if the host reports it.
+    beats: Option<f64>,
+    playing: bool,
+}
 
-            amount: FloatParam::new("Amount", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 }),
-        }
+impl SoutGainRs {
+    /// The lookahead time in samples at the current sample rate.
+    fn lookahead_samples(&self) -> usize {
+        (self.params.lookahead_ms.value() / 1000.0 * self.sample_rate).round() as usize
     }
-}
 
-impl Plugin for SoutGainRs {
-    type BackgroundTask = ();
-    type SysExMessage = ();
+    /// The total latency the lookahead and oversampling add, in samples.
+    fn total_latency_samples(&self) -> u32 {
+        self.lookahead_samples() as u32 + self.params.oversampling.value().latency_samples()
+    }
 
-    const NAME: &'static str = "SoutExGain";
-    const VENDOR: &'static str = "sout";
-    const URL: &'static str = env!("CARGO_PKG_HOMEPAGE");
-    const EMAIL: &'static str = "sout_nantang@outlook.com";
+    /// Allocates the per channel state for `num_channels` channels, and sets up everything that
+    /// depends on the sample rate.
+    fn allocate(&mut self, num_channels: usize) {
+        self.duck_gain_state = vec![1.0; num_channels];
+        self.duck_ramps = (0..num_channels).map(|_| DecimatedRamp::new(1.0)).collect();
+        self.sidechain_envelopes = vec![0.0; num_channels];
+        self.gate_envelopes = vec![0.0; num_channels];
+        self.oversamplers = (0..num_channels).map(|_| Oversampler::default()).collect();
+        self.oversampling = self.params.oversampling.value();
+        let max_lookahead_samples = (MAX_LOOKAHEAD_MS / 1000.0 * self.sample_rate).ceil() as usize;
+        self.lookahead_delays = (0..num_channels)
+            .map(|_| DelayLine::new(max_lookahead_samples))
+            .collect();
+        self.dc_blockers = (0..num_channels)
+            .map(|_| DcBlocker::new(self.sample_rate))
+            .collect();
+        self.crossovers = (0..num_channels)
+            .map(|_| Crossover::new(self.params.crossover_hz.value(), self.sample_rate))
+            .collect();
+        self.band_delays = (0..num_channels)
+            .map(|_| DelayLine::new(OVERSAMPLING_LATENCY as usize))
+            .collect();
+        self.gain_matches = (0..num_channels)
+            .map(|_| GainMatch::new(GAIN_MATCH_WINDOW_MS, self.sample_rate))
+            .collect();
+        self.soft_start_samples = (SOFT_START_MS / 1000.0 * self.sample_rate).round() as usize;
+        self.sidechain_attack_coef = one_pole_coefficient(SIDECHAIN_ATTACK_MS, self.sample_rate);
+        self.sidechain_release_coef = one_pole_coefficient(SIDECHAIN_RELEASE_MS, self.sample_rate);
+        let max_rms_window_samples =
+            (MAX_RMS_WINDOW_MS / 1000.0 * self.sample_rate).ceil() as usize;
+        self.input_rms = RmsWindow::new(max_rms_window_samples);
+        self.output_rms = RmsWindow::new(max_rms_window_samples);
+        self.correlation =
+            Correlation::new((CORRELATION_WINDOW_MS / 1000.0 * self.sample_rate).round() as usize);
+        self.peak_meter_decay_weight =
+            0.25f64.powf((self.sample_rate as f64 * PEAK_METER_DECAY_MS / 1000.0).recip()) as f32;
+    }
 
-    const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+    /// Snaps the envelope's smoothers to their parameters' current values.
+    fn reset_envelope_smoothers(&mut self) {
+        self.amount_smoother.reset(self.params.amount.value());
+        self.release_pow_smoother
+            .reset(self.params.release_pow.value());
+        self.depth_db_smoother.reset(self.params.depth_db.value());
+    }
 
-    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[
-        AudioIOLayout {
-            main_input_channels: NonZeroU32::new(2),
-            main_output_channels: NonZeroU32::new(2),
-            aux_input_ports: &[],
-            aux_output_ports: &[],
-            names: PortNames::const_default(),
-        },
-        AudioIOLayout {
-            main_input_channels: NonZeroU32::new(1),
-            main_output_channels: NonZeroU32::new(1),
-            ..AudioIOLayout::const_default()
-        },
-    ];
+    /// Processes a block of audio while the plugin isn't bypassed, and updates the meters. This is
+    /// everything `process()` does after reading the transport. `next_event_fn` yields the block's
+    /// note events in order. Returns the lowest ducking gain during the block.
+    fn process_block(
+        &mut self,
+        channels: &mut [&mut [f32]],
+        sidechain_input: Option<&[&mut [f32]]>,
+        mut key_output: Option<&mut [&mut [f32]]>,
+        position: BlockPosition,
+        mut next_event_fn: impl FnMut() -> Option<PluginNoteEvent<Self>>,
+    ) -> f32 {
+        // The stereo features treat the channels as left/right pairs. With the mono layout, or if
+        // the host passes a buffer that doesn't match the negotiated layout, they're disabled
+        // instead, and all per channel state is looked up with `get()`.
+        let num_channels = channels.len();
+        let num_samples = channels.first().map_or(0, |channel| channel.len());
+        let stereo = is_stereo(num_channels);
+        let retrigger_on_play = self.params.retrigger_on_play.value();
+        let smooth_length_change = self.params.smooth_length_change.value();
+        let length_fade_samples = (LENGTH_FADE_MS / 1000.0 * self.sample_rate).round() as usize;
+        let anchor = self.params.anchor.value();
+        let sample_duration = 1.0 / self.sample_rate as f64;
+        let frozen = self.params.freeze_when_stopped.value() && !position.playing;
+        let oversampling = self.oversampling;
+        let lookahead_samples = self.lookahead_samples();
+        let emit_key = self.params.emit_key.value();
+        let mut block_min_duck_gain: f32 = 1.0;
+        let mut block_clipped = false;
+        let mut beat_phase = self.meters.beat_phase.load();
+        // The smoothing times are only sampled once per block since computing the coefficients
+        // is relatively expensive
+        let attack_coef = one_pole_coefficient(self.params.attack_ms.value(), self.sample_rate);
+        let release_coef = one_pole_coefficient(self.params.release_ms.value(), self.sample_rate);
+        let invert = self.params.invert.value();
+        let polarity = self.params.polarity.value();
+        let safety_limit = self.params.safety_limit.value();
+        let dc_filter = self.params.dc_filter.value();
+        let band_split = self.params.band_split.value();
+        let gain_match = self.params.gain_match.value();
+        // The crossover's coefficients are only updated once per block since computing them is
+        // relatively expensive
+        let crossover_hz = self.params.crossover_hz.value();
+        for crossover in &mut self.crossovers {
+            crossover.set_frequency(crossover_hz, self.sample_rate);
+        }
+        let listen = self.params.listen.value();
+        let test_tone = self.params.test_tone.value();
+        // A single channel always follows the loudest sidechain channel
+        let stereo_link = self.params.stereo_link.value() || !stereo;
+        let mode = self.params.mode.value();
+        let mut pre_trim_peak = self.meters.pre_trim_peak.load();
+        let mut input_peak = self.meters.input_peak.load();
+        let mut output_peak = self.meters.output_peak.load();
+        let mut input_channel_peaks = self.meters.input_channel_peaks.load_all();
+        let mut output_channel_peaks = self.meters.output_channel_peaks.load_all();
+        // The hold time is converted to samples so it lasts equally long at every sample rate
+        let peak_hold_samples =
+            (self.params.peak_hold_ms.value() / 1000.0 * self.sample_rate).round() as usize;
+        let rms_window_samples =
+            (self.params.rms_window_ms.value() / 1000.0 * self.sample_rate).round() as usize;
+        self.input_rms.set_len(rms_window_samples);
+        self.output_rms.set_len(rms_window_samples);
+        let smoothing_samples =
+            (self.params.smoothing_ms.value() / 1000.0 * self.sample_rate).round() as usize;
+        let smooth_style = self.params.smooth_style.value();
+        let quality_interval = self.params.quality.value().interval();
+        // Rebuilding a table evaluates the shape thousands of times, and the table is only used
+        // once the smoothed values match the ones it was computed for. Under automation the
+        // targets change every block, so the tables are only rebuilt once the smoothers have
+        // settled, and the envelope is computed directly until then. The cycle's length is
+        // computed the same way as in the loop below so the two match exactly.
+        let table_step_beats = self.params.division.value().beats(self.time_signature)
+            * self.params.modifier.value().scale();
+        let table_cycle_beats = self.params.length.value() as f64 * table_step_beats;
+        let shared_settled = !self.params.attack_pow.smoothed.is_smoothing()
+            && !self.params.hold.smoothed.is_smoothing()
+            && !self.params.bias.smoothed.is_smoothing();
+        let settled = shared_settled
+            && !self.params.length.smoothed.is_smoothing()
+            && self.release_pow_smoother.previous_value() == self.params.release_pow.value();
+        let second_settled = shared_settled && !self.params.release_pow2.smoothed.is_smoothing();
+        if settled {
+            self.envelope_table.update(
+                &DuckEnvelope {
+                    shape: self.params.shape.value(),
+                    custom_curve: self
+                        .params
+                        .custom
+                        .value()
+                        .then_some(&self.params.custom_curve_table),
+                    attack_pow: self.params.attack_pow.value(),
+                    release_pow: self.params.release_pow.value(),
+                    amount: self.params.amount.value(),
+                    depth_db: self.params.depth_db.value(),
+                    hold: self.params.hold.value(),
+                    bias: self.params.bias.value(),
+                },
+                table_cycle_beats as f32,
+            );
+        }
+        if self.params.second_env.value() && second_settled {
+            let table_second_cycle_beats = self.params.length2.value() as f64 * table_step_beats;
+            self.second_envelope_table.update(
+                &DuckEnvelope {
+                    shape: self.params.shape.value(),
+                    custom_curve: self
+                        .params
+                        .custom
+                        .value()
+                        .then_some(&self.params.custom_curve_table),
+                    attack_pow: self.params.attack_pow.value(),
+                    release_pow: self.params.release_pow2.value(),
+                    amount: self.params.amount2.value(),
+                    depth_db: self.params.depth_db.value(),
+                    hold: self.params.hold.value(),
+                    bias: self.params.bias.value(),
+                },
+                table_second_cycle_beats as f32,
+            );
+        }
+        let envelope_smoothing_samples =
+            (ENVELOPE_SMOOTHING_MS / 1000.0 * self.sample_rate).round() as usize;
 
-    const MIDI_INPUT: MidiConfig = MidiConfig::None;
-    const SAMPLE_ACCURATE_AUTOMATION: bool = true;
+        let mut next_event = next_event_fn();
+        for sample_idx in 0..num_samples {
+            // Note events are handled on the exact sample they occur on so the duck restarts in
+            // time with the MIDI clip
+            while let Some(event) = next_event {
+                if event.timing() > sample_idx as u32 {
+                    break;
+                }
 
-    fn params(&self) -> Arc<dyn Params> {
-        self.params.clone()
-    }
+                match event {
+                    NoteEvent::NoteOn { .. } => {
+                        if self.params.midi_trigger.value() {
+                            self.accumulated_beat = 0.0;
+                            self.second_beat = 0.0;
+                            self.pair_beat = 0.0;
+                        }
+                    }
+                    NoteEvent::MidiCC { cc, value, .. }
+                        if self.params.midi_amount.value()
+                            && cc as i32 == self.params.amount_cc.value() =>
+                    {
+                        // The CC's ramp starts from wherever the parameter's smoother is
+                        if self.cc_amount_base.is_none() {
+                            self.cc_amount.reset(self.amount_smoother.previous_value());
+                        }
+                        self.cc_amount.set_target(self.sample_rate, value);
+                        self.cc_amount_base = Some(self.params.amount.value());
+                    }
+                    _ => (),
+                }
 
-    fn process(
-        &mut self,
-        buffer: &mut Buffer,
-        _aux: &mut AuxiliaryBuffers,
-        context: &mut impl ProcessContext<Self>,
-    ) -> ProcessStatus {
-        self.tempo = context.transport().tempo.expect("err: cannot get tempo");
+                next_event = next_event_fn();
+            }
 
-        for channel_samples in buffer.iter_samples() {
-            let gain = self.params.gain.smoothed.next();
+            let gain = self
+                .gain_smoother
+                .next(self.params.gain.value(), smoothing_samples);
             let length = self.params.length.smoothed.next();
-            let amount = self.params.amount.smoothed.next();
-            let pow = self.params.pow.smoothed.next();
-
-            for sample in channel_samples {
-                if length > 0 {
-                    let second = context
-                        .transport()
-                        .pos_seconds()
-                        .expect("err: cannot get seconds");
-                    let beat = self.tempo / 60.0 * second % length as f64;
-                    let final_db = -((beat as f32 + 1.0).powf(-pow)) * 50.0 * amount;
-                    *sample *= util::db_to_gain(final_db);
+            let mut amount = self.amount_smoother.next(
+                self.params.amount.value(),
+                smooth_style,
+                envelope_smoothing_samples,
+            );
+            if let Some(base) = self.cc_amount_base {
+                if self.params.midi_amount.value() && self.params.amount.value() == base {
+                    amount = self.cc_amount.next();
+                } else {
+                    // Automation or the web UI changed the amount, which takes over from the CC.
+                    // The parameter's smoother continues from the CC's value so this doesn't
+                    // jump.
+                    let cc_amount = self.cc_amount.previous_value();
+                    self.amount_smoother.reset(cc_amount);
+                    self.cc_amount_base = None;
+                    amount = cc_amount;
                 }
-                *sample *= gain;
             }
-        }
-
-        ProcessStatus::Normal
-    }
+            let release_pow2 = self.params.release_pow2.smoothed.next();
+            let amount2 = self.params.amount2.smoothed.next();
+            let depth_db = self.depth_db_smoother.next(
+                self.params.depth_db.value(),
+                smooth_style,
+                envelope_smoothing_samples,
+            );
+            let hold = self.params.hold.smoothed.next();
+            let bias = self.params.bias.smoothed.next();
+            let floor_db = self.params.floor_db.smoothed.next();
+            let attack_pow = self.params.attack_pow.smoothed.next();
+            let release_pow = self.release_pow_smoother.next(
+                self.params.release_pow.value(),
+                smooth_style,
+                envelope_smoothing_samples,
+            );
+            let rate_hz = self.params.rate_hz.smoothed.next();
+            let mix = self.params.mix.smoothed.next();
+            let output_gain = self.params.output_gain.smoothed.next();
+            let input_gain = self.params.input_gain.smoothed.next();
+            let width = self.params.width.smoothed.next();
+            let low_amount = self.params.low_amount.smoothed.next();
+            let high_amount = self.params.high_amount.smoothed.next();
+            let ceiling = util::db_to_gain(self.params.ceiling_db.smoothed.next());
+            let threshold = util::db_to_gain(self.params.threshold_db.smoothed.next());
+            let tremolo_depth_db = self.params.tremolo_depth_db.smoothed.next();
+            let tremolo_shape = self.params.tremolo_shape.value();
+            let tone_hz = self.params.tone_hz.smoothed.next();
+            let period_ms = self.params.period_ms.smoothed.next();
+            let sync_mode = self.params.sync_mode.value();
+            let free_running = sync_mode != SyncMode::HostTempo;
+            let tempo = self.tempo * self.params.tempo_mult.value().factor();
+            let sidechain = self.params.sidechain.value();
+            let midi_trigger = self.params.midi_trigger.value();
+            let shape = self.params.shape.value();
+            let custom = self.params.custom.value();
+            let phase_offset = self.params.phase_offset.smoothed.next();
+            let swing = self.params.swing.smoothed.next();
+            let swing = if free_running { 0.0 } else { swing };
+            let envelope = DuckEnvelope {
+                shape,
+                custom_curve: custom.then_some(&self.params.custom_curve_table),
+                attack_pow,
+                release_pow,
+                amount,
+                depth_db,
+                hold,
+                bias,
+            };
+            let second_envelope = DuckEnvelope {
+                release_pow: release_pow2,
+                amount: amount2,
+                ..envelope
+            };
+            let second_env = self.params.second_env.value();
+            // The right channel's cycle is offset from the left channel's
+            let stereo_offset = self.params.stereo_offset.smoothed.next();
+            let stereo_offset = if stereo { stereo_offset } else { 0.0 };
+            // The length of a single cycle in quarter note beats. The phase offset is relative to
+            // this, so it stays at the same point in the cycle regardless of the modifier.
+            let step_beats = self.params.division.value().beats(self.time_signature)
+                * self.params.modifier.value().scale();
+            let cycle_beats = length as f64 * step_beats;
+            let second_cycle_beats = self.params.length2.value() as f64 * step_beats;
+            // The audio is delayed by the lookahead, so the tempo synced envelope needs to be
+            // delayed by the same amount to stay on the beat
+            let offset_seconds = (sample_idx as f64 - lookahead_samples as f64) * sample_duration;
+            let second = position.seconds + offset_seconds;
+            if cycle_beats != self.previous_cycle_beats {
+                // The old cycle continues from where it would have been during this sample
+                self.length_fade =
+                    (smooth_length_change && self.previous_cycle_beats > 0.0).then(|| {
+                        LengthFade::new(
+                            self.accumulated_beat,
+                            self.previous_cycle_beats,
+                            length_fade_samples,
+                        )
+                    });
+                self.previous_cycle_beats = cycle_beats;
+            }
 
-    fn editor(&mut self, _async_executor: AsyncExecutor<Self>) -> Option<Box<dyn Editor>> {
-        let params = self.params.clone();
-        let gain_value_changed = self.params.gain_value_changed.clone();
-        let editor = WebViewEditor::new(HTMLSource::String(include_str!("gui.html")), (200, 200))
-            .with_background_color((150, 150, 150, 255))
-            .with_developer_mode(true)
-            .with_keyboard_handler(move |event| {
-                println!("keyboard event: {event:#?}");
-                event.key == Key::Escape
-            })
-            .with_mouse_handler(|event| match event {
-                MouseEvent::DragEntered { .. } => {
-                    println!("drag entered");
-                    EventStatus::AcceptDrop(DropEffect::Copy)
+            // The sidechain followers run per channel, while the tempo synced cycle's phase can
+            // be offset per channel
+            let mut cycle_phase = None;
+            let mut fading_cycle = None;
+            let mut second_phase = None;
+            if sidechain {
+                for (channel_idx, envelope) in self
+                    .sidechain_envelopes
+                    .iter_mut()
+                    .take(num_channels)
+                    .enumerate()
+                {
+                    let level =
+                        sidechain_level(sidechain_input, channel_idx, sample_idx, stereo_link);
+                    follow_peak(
+                        envelope,
+                        level,
+                        self.sidechain_attack_coef,
+                        self.sidechain_release_coef,
+                    );
                 }
-                MouseEvent::DragMoved { .. } => {
-                    println!("drag moved");
-                    EventStatus::AcceptDrop(DropEffect::Copy)
+            } else if length > 0 {
+                if free_running || midi_trigger || retrigger_on_play || anchor != Anchor::SongStart
+                {
+                    self.beat_synced = false;
+                } else if let Some(block_start_beats) = position.beats {
+                    self.accumulated_beat =
+                        advance_beat(block_start_beats, tempo / 60.0, offset_seconds, cycle_beats);
+                    self.second_beat = advance_beat(
+                        block_start_beats,
+                        tempo / 60.0,
+                        offset_seconds,
+                        second_cycle_beats,
+                    );
+                    self.pair_beat = advance_beat(
+                        block_start_beats,
+                        tempo / 60.0,
+                        offset_seconds,
+                        2.0 * cycle_beats,
+                    );
+                    self.beat_synced = true;
+                } else if !self.beat_synced {
+                    // Hosts without a beat position fall back to the position in seconds
+                    self.accumulated_beat = host_cycle_beat(tempo, second, cycle_beats);
+                    self.second_beat = host_cycle_beat(tempo, second, second_cycle_beats);
+                    self.pair_beat = host_cycle_beat(tempo, second, 2.0 * cycle_beats);
+                    self.beat_synced = true;
                 }
-                MouseEvent::DragLeft => {
-                    println!("drag left");
-                    EventStatus::Ignored
+                // The offset shifts the trough by a fraction of the cycle. `rem_euclid()` keeps the
+                // result within the cycle for offsets and pre-roll positions that would otherwise
+                // fall outside of it.
+                cycle_phase = Some(if swing > 0.0 {
+                    let beat = (self.pair_beat + phase_offset as f64 * cycle_beats)
+                        .rem_euclid(2.0 * cycle_beats);
+                    swing_phase((beat / cycle_beats) as f32, swing)
+                } else {
+                    let beat = (self.accumulated_beat + phase_offset as f64 * cycle_beats)
+                        .rem_euclid(cycle_beats);
+                    (beat / cycle_beats) as f32
+                });
+                fading_cycle = self
+                    .length_fade
+                    .as_ref()
+                    .map(|fade| fade.cycle(phase_offset));
+                if second_env {
+                    let beat = (self.second_beat + phase_offset as f64 * second_cycle_beats)
+                        .rem_euclid(second_cycle_beats);
+                    second_phase = Some((beat / second_cycle_beats) as f32);
                 }
-                MouseEvent::DragDropped { data, .. } => {
-                    if let DropData::Files(files) = data {
-                        println!("drag dropped: {:?}", files);
-                    }
-                    EventStatus::AcceptDrop(DropEffect::Copy)
+            }
+            if let Some(phase) = cycle_phase {
+                beat_phase = phase;
+            }
+
+            // The free running modes set how long the whole cycle lasts, regardless of its length
+            // in beats. Otherwise the cycle runs at the host's tempo, starting from the transport
+            // position, the last note on event, or from when playback started.
+            if length > 0 {
+                let beats_per_second = match sync_mode {
+                    SyncMode::FreeHz => cycle_beats * rate_hz as f64,
+                    SyncMode::FreeMs => cycle_beats / (period_ms as f64 / 1000.0),
+                    SyncMode::HostTempo => tempo / 60.0,
+                };
+                self.accumulated_beat = advance_beat(
+                    self.accumulated_beat,
+                    beats_per_second,
+                    sample_duration,
+                    cycle_beats,
+                );
+                self.second_beat = advance_beat(
+                    self.second_beat,
+                    beats_per_second,
+                    sample_duration,
+                    second_cycle_beats,
+                );
+                self.pair_beat = advance_beat(
+                    self.pair_beat,
+                    beats_per_second,
+                    sample_duration,
+                    2.0 * cycle_beats,
+                );
+                if self
+                    .length_fade
+                    .as_mut()
+                    .is_some_and(|fade| !fade.advance(beats_per_second, sample_duration))
+                {
+                    self.length_fade = None;
                 }
-                _ => EventStatus::Ignored,
-            })
-            .with_event_loop(move |ctx, setter, window| {
-                while let Ok(value) = ctx.next_event() {
-                    if let Ok(action) = serde_json::from_value(value) {
-                        match action {
-                            Action::SetGain { value } => {
-                                setter.begin_set_parameter(&params.gain);
-                                setter.set_parameter_normalized(&params.gain, value);
-                                setter.end_set_parameter(&params.gain);
-                            }
-                            Action::SetLength { value } => {
-                                setter.begin_set_parameter(&params.length);
-                                setter.set_parameter(&params.length, value as i32);
-                                setter.end_set_parameter(&params.length);
-                            }
-                            Action::SetPow { value } => {
-                                setter.begin_set_parameter(&params.pow);
-                                setter.set_parameter_normalized(&params.pow, value);
-                                setter.end_set_parameter(&params.pow);
-                            }
-                            Action::SetAmount { value } => {
-                                setter.begin_set_parameter(&params.amount);
-                                setter.set_parameter_normalized(&params.amount, value);
-                                setter.end_set_parameter(&params.amount);
+            }
+
+            // The phase keeps running across blocks and frequency changes so the tone doesn't
+            // click
+            let tone = test_tone.then(|| {
+                let tone = (self.tone_phase * std::f64::consts::TAU).sin() as f32;
+                self.tone_phase = (self.tone_phase + tone_hz as f64 * sample_duration).fract();
+                tone * TEST_TONE_LEVEL
+            });
+
+            let mut pre_trim_amplitude: f32 = 0.0;
+            let mut input_amplitude: f32 = 0.0;
+            let mut input_square_sum: f32 = 0.0;
+            let mut output_square_sum: f32 = 0.0;
+            let mut output_amplitude: f32 = 0.0;
+            let mut scope_value: f32 = 0.0;
+            for (channel_idx, channel) in channels.iter_mut().enumerate() {
+                let sample = &mut channel[sample_idx];
+                // The tone replaces the input before anything else, so it's processed like any
+                // other source
+                if let Some(tone) = tone {
+                    *sample = tone;
+                }
+                pre_trim_amplitude = pre_trim_amplitude.max(sample.abs());
+                *sample = trim_input(*sample, input_gain, polarity);
+                let (delayed, gate_level) = lookahead(
+                    *sample,
+                    self.gate_envelopes
+                        .get_mut(channel_idx)
+                        .filter(|_| mode == Mode::Gate),
+                    self.lookahead_delays.get_mut(channel_idx),
+                    lookahead_samples,
+                    self.sidechain_attack_coef,
+                    self.sidechain_release_coef,
+                );
+                *sample = delayed;
+                input_amplitude = input_amplitude.max(sample.abs());
+                if let Some(peak) = input_channel_peaks.get_mut(channel_idx) {
+                    *peak = decay_peak(*peak, sample.abs(), self.peak_meter_decay_weight);
+                }
+                input_square_sum += *sample * *sample;
+
+                let target_gain = match mode {
+                    Mode::Duck => {
+                        // The envelope is only evaluated every few samples at the lower quality
+                        // settings
+                        let evaluate = || {
+                            let phase = cycle_phase
+                                .map(|phase| channel_phase(phase, channel_idx, stereo_offset));
+                            let sidechain_envelope = sidechain.then(|| {
+                                self.sidechain_envelopes
+                                    .get(channel_idx)
+                                    .copied()
+                                    .unwrap_or(0.0)
+                            });
+                            let mut final_db = duck_db(
+                                phase,
+                                sidechain_envelope,
+                                &self.envelope_table,
+                                &envelope,
+                                cycle_beats as f32,
+                            );
+                            if let Some(fading) = fading_cycle {
+                                let phase = channel_phase(fading.phase, channel_idx, stereo_offset);
+                                let fading_db = self.envelope_table.duck_db(
+                                    &envelope,
+                                    phase,
+                                    fading.cycle_beats,
+                                );
+                                final_db = fading.blend(final_db, fading_db);
                             }
-                            Action::SetSize { width, height } => {
-                                ctx.resize(window, width, height);
+                            if let Some(phase) = second_phase {
+                                let phase = channel_phase(phase, channel_idx, stereo_offset);
+                                let second_db = self.second_envelope_table.duck_db(
+                                    &second_envelope,
+                                    phase,
+                                    second_cycle_beats as f32,
+                                );
+                                final_db = cascade_duck_db(final_db, second_db);
                             }
-                            Action::Init => {
-                                let _ = ctx.send_json(json!({
-                                    "type": "set_size",
-                                    "width": ctx.width.load(Ordering::Relaxed),
-                                    "height": ctx.height.load(Ordering::Relaxed)
-                                }));
+                            util::db_to_gain(limit_duck_db(final_db, floor_db, invert))
+                        };
+                        match self.duck_ramps.get_mut(channel_idx) {
+                            Some(ramp) => ramp.next(quality_interval, evaluate),
+                            None => evaluate(),
+                        }
+                    }
+                    Mode::Gate => gate_gain(gate_level, threshold),
+                    // The tremolo follows the same cycle as the ducking envelope, including the
+                    // stereo offset, but it's bipolar and never stops
+                    Mode::Tremolo => match cycle_phase {
+                        Some(phase) => {
+                            let phase = channel_phase(phase, channel_idx, stereo_offset);
+                            let mut lfo = tremolo_lfo(tremolo_shape, phase);
+                            if let Some(fading) = fading_cycle {
+                                let phase = channel_phase(fading.phase, channel_idx, stereo_offset);
+                                lfo = fading.blend(lfo, tremolo_lfo(tremolo_shape, phase));
                             }
+                            util::db_to_gain(lfo * tremolo_depth_db)
                         }
-                    } else {
-                        panic!("Invalid action received from web UI.")
+                        None => 1.0,
+                    },
+                };
+                // While frozen the gain eases back to unity at the release rate instead of
+                // jumping there
+                let target_gain = if frozen { 1.0 } else { target_gain };
+                let (previous_duck_gain, duck_gain) =
+                    match self.duck_gain_state.get_mut(channel_idx) {
+                        Some(state) => {
+                            let previous = *state;
+                            // The attack time applies when moving away from unity gain, which is
+                            // upwards for an inverted envelope. The gate uses it for opening.
+                            let attacking = match mode {
+                                Mode::Duck => (target_gain < *state) != invert,
+                                Mode::Gate => target_gain > *state,
+                                Mode::Tremolo => target_gain < *state,
+                            };
+                            let coef = if attacking { attack_coef } else { release_coef };
+                            *state = target_gain + coef * (*state - target_gain);
+                            (previous, *state)
+                        }
+                        None => (target_gain, target_gain),
+                    };
+                block_min_duck_gain = block_min_duck_gain.min(duck_gain);
+                // The key follows the gain before oversampling, so with oversampling enabled it's
+                // ahead of the main output by the oversampling latency
+                if let Some(key) = key_output
+                    .as_mut()
+                    .filter(|_| emit_key)
+                    .and_then(|channels| channels.get_mut(channel_idx))
+                    .and_then(|channel| channel.get_mut(sample_idx))
+                {
+                    *key = 1.0 - duck_gain;
+                }
+
+                // When oversampled, the duck gain ramps from the previous sample's gain to this
+                // sample's gain at the higher rate
+                let band_filters = self
+                    .crossovers
+                    .get_mut(channel_idx)
+                    .zip(self.band_delays.get_mut(channel_idx))
+                    .filter(|_| band_split);
+                let (dry, wet) = match (self.oversamplers.get_mut(channel_idx), band_filters) {
+                    // Each band's gain is the ducking gain scaled by its amount in decibels. Only
+                    // the low band is oversampled, since that's where the pumping happens.
+                    (Some(oversampler), Some((crossover, band_delay))) => {
+                        let (low, high) = crossover.split(*sample);
+                        let low = oversampler.process(
+                            oversampling,
+                            low,
+                            previous_duck_gain.powf(low_amount),
+                            duck_gain.powf(low_amount),
+                        );
+                        let high = band_delay
+                            .process(high, oversampling.latency_samples() as usize)
+                            * duck_gain.powf(high_amount);
+                        (oversampler.delay_dry(oversampling, *sample), low + high)
+                    }
+                    (Some(oversampler), None) => (
+                        oversampler.delay_dry(oversampling, *sample),
+                        oversampler.process(oversampling, *sample, previous_duck_gain, duck_gain),
+                    ),
+                    (None, _) => (*sample, *sample * duck_gain),
+                };
+                // The average keeps following the envelope while gain matching is disabled, so
+                // enabling it starts with the right makeup gain
+                let makeup = match self.gain_matches.get_mut(channel_idx) {
+                    Some(matcher) => matcher.process(duck_gain),
+                    None => 1.0,
+                };
+                *sample = wet * gain;
+                if gain_match {
+                    *sample *= makeup;
+                }
+                match listen {
+                    Listen::Normal => *sample = dry * (1.0 - mix) + *sample * mix,
+                    Listen::WetOnly => (),
+                    // Layouts without a sidechain input monitor silence
+                    Listen::SidechainOnly => {
+                        *sample = sidechain_input
+                            .and_then(|channels| channels.get(channel_idx % channels.len().max(1)))
+                            .and_then(|channel| channel.get(sample_idx))
+                            .copied()
+                            .unwrap_or(0.0);
                     }
                 }
+                *sample *= output_gain;
+            }
 
-                if gain_value_changed.swap(false, Ordering::Relaxed) {
-                    let _ = ctx.send_json(json!({
-                        "type": "param_change",
-                        "param": "gain",
-                        "value": params.gain.unmodulated_normalized_value(),
-                        "text": params.gain.to_string()
-                    }));
+            // The width is applied to the final signal, so it needs both channels at once. Only
+            // a plain stereo pair has a meaningful side signal.
+            if let [left, right] = &mut *channels {
+                (left[sample_idx], right[sample_idx]) =
+                    stereo_width(left[sample_idx], right[sample_idx], width);
+            }
+
+            let soft_start_gain = if self.soft_start_remaining > 0 {
+                self.soft_start_remaining -= 1;
+                1.0 - self.soft_start_remaining as f32 / self.soft_start_samples as f32
+            } else {
+                1.0
+            };
+
+            for (channel_idx, channel) in channels.iter_mut().enumerate() {
+                let sample = &mut channel[sample_idx];
+                *sample *= soft_start_gain;
+                // The filter keeps running while disabled so enabling it doesn't start from stale
+                // state. It comes before the limiter so the ceiling still holds.
+                if let Some(blocker) = self.dc_blockers.get_mut(channel_idx) {
+                    let filtered = blocker.process(*sample);
+                    if dc_filter {
+                        *sample = filtered;
+                    }
                 }
-            });
+                if safety_limit {
+                    *sample = soft_clip(*sample, ceiling);
+                }
+                output_amplitude = output_amplitude.max(sample.abs());
+                if let Some(peak) = output_channel_peaks.get_mut(channel_idx) {
+                    *peak = decay_peak(*peak, sample.abs(), self.peak_meter_decay_weight);
+                }
+                output_square_sum += *sample * *sample;
+                if sample.abs() > scope_value.abs() {
+                    scope_value = *sample;
+                }
+            }
 
-        Some(Box::new(editor))
+            block_clipped |= output_amplitude > 1.0;
+            pre_trim_peak = decay_peak(
+                pre_trim_peak,
+                pre_trim_amplitude,
+                self.peak_meter_decay_weight,
+            );
+            input_peak = decay_peak(input_peak, input_amplitude, self.peak_meter_decay_weight);
+            output_peak = decay_peak(output_peak, output_amplitude, self.peak_meter_decay_weight);
+            self.input_hold.process(
+                input_amplitude,
+                peak_hold_samples,
+                self.peak_meter_decay_weight,
+            );
+            self.output_hold.process(
+                output_amplitude,
+                peak_hold_samples,
+                self.peak_meter_decay_weight,
+            );
+            // The RMS meters average over all channels
+            self.input_rms
+                .push(input_square_sum / num_channels.max(1) as f32);
+            self.output_rms
+                .push(output_square_sum / num_channels.max(1) as f32);
+            // Correlation only means something for a single stereo pair
+            if let [left, right] = &*channels {
+                self.correlation.push(left[sample_idx], right[sample_idx]);
+            }
+
+            // Every point in the scope holds the largest magnitude sample out of
+            // `SCOPE_DECIMATION` samples, across all channels
+            let point = &mut self.scope_frame[self.scope_point];
+            if scope_value.abs() > point.abs() {
+                *point = scope_value;
+            }
+            self.scope_sample += 1;
+            if self.scope_sample == SCOPE_DECIMATION {
+                self.scope_sample = 0;
+                self.scope_point += 1;
+                if self.scope_point == SCOPE_POINTS {
+                    self.meters.scope.push(&self.scope_frame);
+                    self.scope_frame = [0.0; SCOPE_POINTS];
+                    self.scope_point = 0;
+                }
+            }
+        }
+
+        self.meters
+            .gain_reduction_db
+            .store(util::gain_to_db(block_min_duck_gain));
+        self.meters.pre_trim_peak.store(pre_trim_peak);
+        self.meters.input_peak.store(input_peak);
+        self.meters.output_peak.store(output_peak);
+        let meter_channels = num_channels.min(MAX_METER_CHANNELS);
+        self.meters
+            .input_channel_peaks
+            .store(&input_channel_peaks[..meter_channels]);
+        self.meters
+            .output_channel_peaks
+            .store(&output_channel_peaks[..meter_channels]);
+        self.meters.input_hold.store(self.input_hold.value());
+        self.meters.output_hold.store(self.output_hold.value());
+        self.meters.input_rms.store(self.input_rms.rms());
+        self.meters.output_rms.store(self.output_rms.rms());
+        self.meters.correlation.store(if num_channels == 2 {
+            self.correlation.value()
+        } else {
+            f32::NAN
+        });
+        self.meters.beat_phase.store(beat_phase);
+        if block_clipped {
+            self.meters.clipped.store(true, Ordering::Relaxed);
+        }
+
+        block_min_duck_gain
     }
+}
 
-    fn deactivate(&mut self) {}
+/// Soft clips `sample` so its magnitude never exceeds `ceiling`. Samples below `SOFT_CLIP_KNEE`
+/// times the ceiling pass through unchanged, and louder samples are smoothly squashed into the
+/// remaining headroom.
+fn soft_clip(sample: f32, ceiling: f32) -> f32 {
+    let knee = ceiling * SOFT_CLIP_KNEE;
+    let magnitude = sample.abs();
+    if magnitude <= knee {
+        return sample;
+    }
+
+    let headroom = ceiling - knee;
+    let clipped = knee + headroom * ((magnitude - knee) / headroom).tanh();
+    clipped.copysign(sample)
 }
 
-impl ClapPlugin for SoutGainRs {
-    const CLAP_ID: &'static str = "org.eu.sout.audio.exgainwv";
-    const CLAP_DESCRIPTION: Option<&'static str> = Some("it is a gain plugin written by rust");
-    const CLAP_MANUAL_URL: Option<&'static str> = Some(Self::URL);
-    const CLAP_SUPPORT_URL: Option<&'static str> = None;
-    const CLAP_FEATURES: &'static [ClapFeature] = &[
-        ClapFeature::AudioEffect,
-        ClapFeature::Stereo,
-        ClapFeature::Mono,
-        ClapFeature::Utility,
-    ];
+/// Applies the input trim to `sample` and flips its polarity when `polarity` is set. This is the
+/// first thing that happens to the input.
+fn trim_input(sample: f32, input_gain: f32, polarity: bool) -> f32 {
+    let sample = sample * input_gain;
+    if polarity {
+        -sample
+    } else {
+        sample
+    }
 }
 
-impl Vst3Plugin for SoutGainRs {
-    const VST3_CLASS_ID: [u8; 16] = *b"SoutGainWebView!";
-    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
-        &[Vst3SubCategory::Fx, Vst3SubCategory::Tools];
+/// Scales the side signal of a left/right pair by `width` through a mid/side conversion. A mono
+/// signal has no side signal, so it passes through unchanged at any width.
+fn stereo_width(left: f32, right: f32, width: f32) -> (f32, f32) {
+    let mid = (left + right) * 0.5;
+    let side = (left - right) * 0.5 * width;
+    (mid + side, mid - side)
 }
 
+/// A human readable label for a cycle of `length` steps of `division`, like "2 bars" or
+/// "3 × 1/8T". The web UI shows this instead of the `length` parameter's own value, since that
+/// doesn't know about the division and modifier.
+fn length_label(length: i32, division: Division, modifier: Modifier) -> String {
+    if length <= 0 {
+        return String::from("Off");
+    }
+
+    let suffix = match modifier {
+        Modifier::Straight => "",
+        Modifier::Triplet => "T",
+        Modifier::Dotted => ".",
+    };
+    match division {
+        Division::Bar if length == 1 => format!("1 bar{suffix}"),
+        Division::Bar => format!("{length} bars{suffix}"),
+        _ => {
+            let note = Division::variants()[division.to_index()];
+            format!("{length} × {note}{suffix}")
+        }
+    }
+}
+
+/// Moves a peak envelope follower towards `level`, using `attack_coef` when the level rises above
+/// the follower and `release_coef` when it falls below it.
+fn follow_peak(envelope: &mut f32, level: f32, attack_coef: f32, release_coef: f32) {
+    let coef = if level > *envelope {
+        attack_coef
+    } else {
+        release_coef
+    };
+    *envelope = level + coef * (*envelope - level);
+}
+
+/// Feeds `sample` to the gate's level `detector` and delays it by the lookahead. The detector
+/// listens to the signal before the delay, so with lookahead the gate opens ahead of the
+/// transients. Returns the delayed sample and the detected level, which is just the sample's own
+/// level without a detector.
+fn lookahead(
+    sample: f32,
+    detector: Option<&mut f32>,
+    delay: Option<&mut DelayLine>,
+    lookahead_samples: usize,
+    attack_coef: f32,
+    release_coef: f32,
+) -> (f32, f32) {
+    let level = match detector {
+        Some(detector) => {
+            follow_peak(detector, sample.abs(), attack_coef, release_coef);
+            *detector
+        }
+        None => sample.abs(),
+    };
+    let delayed = match delay {
+        Some(delay) => delay.process(sample, lookahead_samples),
+        None => sample,
+    };
+
+    (delayed, level)
+}
+
+/// The ducking mode's gain change in decibels, before it gets inverted or limited by the floor.
+/// `phase` is the channel's position within the tempo synced cycle while the cycle is running,
+/// and `sidechain_envelope` is the channel's sidechain follower level while following the
+/// sidechain. The tempo synced envelope is looked up from `table` when possible.
+fn duck_db(
+    phase: Option<f32>,
+    sidechain_envelope: Option<f32>,
+    table: &EnvelopeTable,
+    envelope: &DuckEnvelope,
+    cycle_beats: f32,
+) -> f32 {
+    match (phase, sidechain_envelope) {
+        (Some(phase), _) => table.duck_db(envelope, phase, cycle_beats),
+        // Higher `release_pow` values make only the loudest peaks duck fully, just like they make
+        // the tempo synced curve recover faster
+        (None, Some(level)) => {
+            -level.min(1.0).powf(envelope.release_pow / 10.0) * envelope.depth_db * envelope.amount
+        }
+        (None, None) => 0.0,
+    }
+}
+
+/// Whether a buffer with `num_channels` channels can be treated as left/right pairs for the stereo
+/// features.
+fn is_stereo(num_channels: usize) -> bool {
+    num_channels >= 2 && num_channels % 2 == 0
+}
+
+/// The position within the cycle for channel `channel_idx` at `phase`. The channels are treated as
+/// left/right pairs, and each right channel's cycle runs `stereo_offset` cycles ahead of its left
+/// channel's.
+fn channel_phase(phase: f32, channel_idx: usize, stereo_offset: f32) -> f32 {
+    (phase + (channel_idx % 2) as f32 * stereo_offset).fract()
+}
+
+/// Layers the second envelope's gain change on top of the first envelope's. Adding the changes in
+/// decibels multiplies the two envelopes' gains.
+fn cascade_duck_db(first_db: f32, second_db: f32) -> f32 {
+    first_db + second_db
+}
+
+/// The second envelope's phase at `phase` through the first envelope's cycle, for cycles that
+/// started together.
+fn second_cycle_phase(phase: f32, cycle_beats: f32, second_cycle_beats: f32) -> f32 {
+    (phase * cycle_beats / second_cycle_beats).fract()
+}
+
+/// The gate mode's target gain. The gate is fully open while the detector's `level` is at or
+/// above `threshold`, and mutes the signal below it.
+fn gate_gain(level: f32, threshold: f32) -> f32 {
+    if level >= threshold {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// The web UI's HTML. This is read from the file at `GUI_PATH_ENV_VAR` when it's set, and falls
+/// back to the embedded `gui.html` when it isn't or when the file can't be read.
+fn html_source() -> HTMLSource {
+    let Some(path) = std::env::var_os(GUI_PATH_ENV_VAR).filter(|path| !path.is_empty()) else {
+        return HTMLSource::String(include_str!("gui.html"));
+    };
+
+    match std::fs::read_to_string(&path) {
+        // The web view needs a static string. This leaks a copy of the file every time the editor
+        // is opened, which is fine for development.
+        Ok(html) => HTMLSource::String(html.leak()),
+        Err(err) => {
+            nih_log!("Could not read the web UI from {:?}: {}", path, err);
+            HTMLSource::String(include_str!("gui.html"))
+        }
+    }
+}
+
+/// Whether `id` looks like a reverse domain name, like `com.example.plugin`. That's at least two
+/// non-empty segments separated by dots, made up of ASCII letters, digits, `-`, and `_`.
+const fn is_reverse_dns(id: &str) -> bool {
+    let bytes = id.as_bytes();
+    let mut segments = 1;
+    let mut segment_len = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'.' if segment_len == 0 => return false,
+            b'.' => {
+                segments += 1;
+                segment_len = 0;
+            }
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' => segment_len += 1,
+            _ => return false,
+        }
+        i += 1;
+    }
+
+    segments >= 2 && segment_len > 0
+}
+
+/// Opens `url` in the system's default browser. This returns as soon as the browser has been
+/// started.
+fn open_url(url: &str) -> std::io::Result<()> {
+    #[cfg(target_os = "windows")]
+    let program = "explorer";
+    #[cfg(target_os = "macos")]
+    let program = "open";
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let program = "xdg-open";
+
+    let mut child = Command::new(program).arg(url).spawn()?;
+    // The process is waited on from another thread so it doesn't linger as a zombie
+    std::thread::spawn(move || {
+        let _ = child.wait();
+    });
+
+    Ok(())
+}
+
+/// The coefficient for a one-pole filter that reaches about 63% of a step change in `time_ms`.
+fn one_pole_coefficient(time_ms: f32, sample_rate: f32) -> f32 {
+    (-1.0 / (time_ms / 1000.0 * sample_rate)).exp()
+}
+
+/// The level the sidechain follower for output channel `channel_idx` follows at `sample_idx`. When
+/// `linked`, every channel follows the loudest sidechain channel so the ducking doesn't shift the
+/// stereo image. Otherwise each channel follows its own sidechain channel, with layouts that have
+/// more channels than the sidechain reusing its channels in order.
+fn sidechain_level(
+    channels: Option<&[&mut [f32]]>,
+    channel_idx: usize,
+    sample_idx: usize,
+    linked: bool,
+) -> f32 {
+    let Some(channels) = channels else {
+        return 0.0;
+    };
+
+    if linked {
+        channels
+            .iter()
+            .filter_map(|channel| channel.get(sample_idx))
+            .map(|sample| sample.abs())
+            .fold(0.0, f32::max)
+    } else {
+        channels
+            .get(channel_idx % channels.len().max(1))
+            .and_then(|channel| channel.get(sample_idx))
+            .map_or(0.0, |sample| sample.abs())
+    }
+}
+
+/// Sets the envelope's parameters to random values within musically useful bounds. The amount
+/// and depth are kept high enough that the result is always clearly audible, and the cycle always
+/// has a length.
+fn randomize_patch(params: &GainParams, setter: &ParamSetter, rng: &mut Rng) {
+    let length = params.length.preview_normalized(1 + rng.index(4) as i32);
+    set_normalized_from_ui(setter, &params.length, length);
+
+    // Whole notes and sixteenths are either too slow or too fast to be useful as a starting point
+    let division = Division::from_index(1 + rng.index(3));
+    setter.begin_set_parameter(&params.division);
+    setter.set_parameter(&params.division, division);
+    setter.end_set_parameter(&params.division);
+
+    let shape = EnvShape::from_index(rng.index(EnvShape::variants().len()));
+    setter.begin_set_parameter(&params.shape);
+    setter.set_parameter(&params.shape, shape);
+    setter.end_set_parameter(&params.shape);
+
+    set_plain_from_ui(setter, &params.amount, rng.range(0.4, 1.0));
+    set_plain_from_ui(setter, &params.release_pow, rng.range(5.0, 15.0));
+    set_plain_from_ui(setter, &params.attack_pow, rng.range(5.0, 15.0));
+    set_plain_from_ui(setter, &params.depth_db, rng.range(24.0, 60.0));
+    set_plain_from_ui(setter, &params.hold, rng.range(0.0, 0.25));
+    set_plain_from_ui(setter, &params.bias, rng.range(0.35, 0.65));
+}
+
+/// The index into the snapshots for the slot named `slot`, either `A` or `B`.
+fn snapshot_index(slot: char) -> Option<usize> {
+    match slot.to_ascii_uppercase() {
+        'A' => Some(0),
+        'B' => Some(1),
+        _ => None,
+    }
+}
+
+/// The `snapshots` message telling the web UI which snapshot slots have been stored.
+fn snapshots_message(snapshots: &[Option<Preset>; 2]) -> Value {
+    json!({
+        "type": "snapshots",
+        "stored": snapshots.iter().map(Option::is_some).collect::<Vec<_>>()
+    })
+}
+
+/// Creates a parameter callback that sets `flag` whenever the parameter's value changes. The
+/// editor's event loop clears the flag again after notifying the web UI.
+fn change_flag_callback<T>(flag: &Arc<AtomicBool>) -> Arc<dyn Fn(T) + Send + Sync> {
+    let flag = flag.clone();
+    Arc::new(move |_: T| {
+        flag.store(true, Ordering::Relaxed);
+    })
+}
+
+/// Formats the `length` parameter for the host. A step's duration depends on the division and
+/// modifier, which this formatter doesn't know about, so only the web UI shows the full
+/// `length_label()`.
+fn v2s_length_steps() -> Arc<dyn Fn(i32) -> String + Send + Sync> {
+    Arc::new(|length| match length {
+        ..=0 => String::from("Off"),
+        1 => String::from("1 step"),
+        _ => format!("{length} steps"),
+    })
+}
+
+/// Parses the strings produced by `v2s_length_steps()`, as well as plain numbers.
+fn s2v_length_steps() -> Arc<dyn Fn(&str) -> Option<i32> + Send + Sync> {
+    Arc::new(|string| {
+        let string = string.trim();
+        if string.eq_ignore_ascii_case("off") {
+            return Some(0);
+        }
+
+        let number = string
+            .trim_end_matches(|c: char| c.is_alphabetic())
+            .trim_end();
+        number.parse().ok()
+    })
+}
+
+/// The peaks of the individual channels in decibels, as sent with the `levels` message.
+fn channel_peaks_db(peaks: &ChannelPeaks) -> Vec<f32> {
+    peaks.load().into_iter().map(util::gain_to_db).collect()
+}
+
+/// The `param_change` message sent to the web UI when a parameter's value has changed.
+fn param_change<P: Param + Display>(id: &str, param: &P) -> Value {
+    json!({
+        "type": "param_change",
+        "param": id,
+        "value": param.unmodulated_normalized_value(),
+        "text": param.to_string()
+    })
+}
+
+/// The `param_meta` message sent to the web UI when it asks about a parameter's range. This lets
+/// the web UI build controls for a parameter without hardcoding its range. Enum and boolean
+/// parameters report their range as indices.
+fn param_meta<P: Param>(id: &str, param: &P) -> Value {
+    let param_ptr = param.as_ptr();
+    // SAFETY: The pointer points to `param`, which is borrowed for the duration of this call
+    let (min, max, default) = unsafe {
+        (
+            param_ptr.preview_plain(0.0),
+            param_ptr.preview_plain(1.0),
+            param_ptr.preview_plain(param.default_normalized_value()),
+        )
+    };
+
+    json!({
+        "type": "param_meta",
+        "id": id,
+        "min": min,
+        "max": max,
+        "default": default,
+        "unit": param.unit()
+    })
+}
+
+/// A kind of message about a single parameter, so `GainParams::param_message()` only needs to map
+/// the web UI ids to parameters once.
+trait ParamMessage {
+    fn build<P: Param + Display>(id: &str, param: &P) -> Value;
+}
+
+/// Builds `param_change()` messages.
+struct ParamChange;
+
+impl ParamMessage for ParamChange {
+    fn build<P: Param + Display>(id: &str, param: &P) -> Value {
+        param_change(id, param)
+    }
+}
+
+/// Builds `param_meta()` messages.
+struct ParamMeta;
+
+impl ParamMessage for ParamMeta {
+    fn build<P: Param + Display>(id: &str, param: &P) -> Value {
+        param_meta(id, param)
+    }
+}
+
+/// Sets a parameter to a normalized value received from the web UI. Values outside of the
+/// normalized range are clamped, and NaN values are ignored so they can't end up in the audio
+/// buffer.
+fn set_normalized_from_ui<P: Param>(setter: &ParamSetter, param: &P, normalized: f32) {
+    if normalized.is_nan() {
+        return;
+    }
+
+    setter.begin_set_parameter(param);
+    setter.set_parameter_normalized(param, normalized.clamp(0.0, 1.0));
+    setter.end_set_parameter(param);
+}
+
+/// Sets a parameter to a plain value received from the web UI, like a gain in decibels. Values
+/// outside of the parameter's range are clamped, and NaN values are ignored.
+fn set_plain_from_ui(setter: &ParamSetter, param: &FloatParam, plain: f32) {
+    if plain.is_finite() {
+        set_normalized_from_ui(setter, param, param.preview_normalized(plain));
+    }
+}
+
+/// The `amount`, `release_pow`, and `depth_db` values for a position of the intensity macro. Along
+/// with the amount, the duck gets deeper and recovers more slowly towards the top of the range.
+/// The midpoint matches the parameters' defaults.
+fn intensity_targets(intensity: f32) -> (f32, f32, f32) {
+    let amount = intensity.clamp(0.0, 1.0);
+    let release_pow = 15.0 - 10.0 * amount;
+    let depth_db = 40.0 + 20.0 * amount;
+
+    (amount, release_pow, depth_db)
+}
+
+impl GainParams {
+    /// The ids the web UI uses for each parameter, paired with the flag that is set when that
+    /// parameter's value changes.
+    fn change_flags(&self) -> [(&'static str, &Arc<AtomicBool>); 68] {
+        [
+            ("gain", &self.gain_value_changed),
+            ("smoothing_ms", &self.smoothing_ms_value_changed),
+            ("smooth_style", &self.smooth_style_value_changed),
+            ("length", &self.length_value_changed),
+            ("release_pow", &self.release_pow_value_changed),
+            ("amount", &self.amount_value_changed),
+            ("second_env", &self.second_env_value_changed),
+            ("length2", &self.length2_value_changed),
+            ("release_pow2", &self.release_pow2_value_changed),
+            ("amount2", &self.amount2_value_changed),
+            ("sync_mode", &self.sync_mode_value_changed),
+            ("anchor", &self.anchor_value_changed),
+            ("rate_hz", &self.rate_hz_value_changed),
+            ("period_ms", &self.period_ms_value_changed),
+            ("mix", &self.mix_value_changed),
+            ("output_gain", &self.output_gain_value_changed),
+            ("input_gain", &self.input_gain_value_changed),
+            ("width", &self.width_value_changed),
+            ("sidechain", &self.sidechain_value_changed),
+            ("midi_trigger", &self.midi_trigger_value_changed),
+            ("midi_amount", &self.midi_amount_value_changed),
+            ("amount_cc", &self.amount_cc_value_changed),
+            ("shape", &self.shape_value_changed),
+            ("phase_offset", &self.phase_offset_value_changed),
+            ("swing", &self.swing_value_changed),
+            ("division", &self.division_value_changed),
+            ("modifier", &self.modifier_value_changed),
+            ("retrigger_on_play", &self.retrigger_on_play_value_changed),
+            (
+                "freeze_when_stopped",
+                &self.freeze_when_stopped_value_changed,
+            ),
+            (
+                "smooth_length_change",
+                &self.smooth_length_change_value_changed,
+            ),
+            ("stereo_offset", &self.stereo_offset_value_changed),
+            ("bypass", &self.bypass_value_changed),
+            ("attack_ms", &self.attack_ms_value_changed),
+            ("release_ms", &self.release_ms_value_changed),
+            ("invert", &self.invert_value_changed),
+            ("polarity", &self.polarity_value_changed),
+            ("depth_db", &self.depth_db_value_changed),
+            ("hold", &self.hold_value_changed),
+            ("oversampling", &self.oversampling_value_changed),
+            ("quality", &self.quality_value_changed),
+            ("lookahead_ms", &self.lookahead_ms_value_changed),
+            ("safety_limit", &self.safety_limit_value_changed),
+            ("ceiling_db", &self.ceiling_db_value_changed),
+            ("dc_filter", &self.dc_filter_value_changed),
+            ("band_split", &self.band_split_value_changed),
+            ("crossover_hz", &self.crossover_hz_value_changed),
+            ("low_amount", &self.low_amount_value_changed),
+            ("high_amount", &self.high_amount_value_changed),
+            ("gain_match", &self.gain_match_value_changed),
+            ("cc_output", &self.cc_output_value_changed),
+            ("cc_number", &self.cc_number_value_changed),
+            ("floor_db", &self.floor_db_value_changed),
+            ("attack_pow", &self.attack_pow_value_changed),
+            ("rms_window_ms", &self.rms_window_ms_value_changed),
+            ("peak_hold_ms", &self.peak_hold_ms_value_changed),
+            ("mode", &self.mode_value_changed),
+            ("threshold_db", &self.threshold_db_value_changed),
+            ("tremolo_depth_db", &self.tremolo_depth_db_value_changed),
+            ("tremolo_shape", &self.tremolo_shape_value_changed),
+            ("intensity", &self.intensity_value_changed),
+            ("listen", &self.listen_value_changed),
+            ("test_tone", &self.test_tone_value_changed),
+            ("tone_hz", &self.tone_hz_value_changed),
+            ("stereo_link", &self.stereo_link_value_changed),
+            ("emit_key", &self.emit_key_value_changed),
+            ("tempo_mult", &self.tempo_mult_value_changed),
+            ("bias", &self.bias_value_changed),
+            ("custom", &self.custom_value_changed),
+        ]
+    }
+
+    /// Builds a `full_state` message containing the `param_change` message for every parameter,
+    /// so the web UI can initialize all of its controls at once.
+    fn full_state_message(&self) -> Value {
+        let params: Vec<Value> = self
+            .change_flags()
+            .iter()
+            .filter_map(|(id, _)| self.param_change_message(id))
+            .collect();
+
+        json!({
+            "type": "full_state",
+            "params": params
+        })
+    }
+
+    /// Builds the `param_change` message for the parameter with the web UI id `id`, if it exists.
+    fn param_change_message(&self, id: &str) -> Option<Value> {
+        let mut message = self.param_message::<ParamChange>(id)?;
+        if id == "length" {
+            message["text"] = Value::String(length_label(
+                self.length.value(),
+                self.division.value(),
+                self.modifier.value(),
+            ));
+        }
+
+        Some(message)
+    }
+
+    /// Builds the `param_meta` message describing the parameter with the web UI id `id`, if it
+    /// exists.
+    fn param_meta_message(&self, id: &str) -> Option<Value> {
+        self.param_message::<ParamMeta>(id)
+    }
+
+    /// Builds a message of kind `M` for the parameter with the web UI id `id`, if it exists.
+    fn param_message<M: ParamMessage>(&self, id: &str) -> Option<Value> {
+        match id {
+            "gain" => Some(M::build(id, &self.gain)),
+            "smoothing_ms" => Some(M::build(id, &self.smoothing_ms)),
+            "smooth_style" => Some(M::build(id, &self.smooth_style)),
+            "length" => Some(M::build(id, &self.length)),
+            "release_pow" => Some(M::build(id, &self.release_pow)),
+            "amount" => Some(M::build(id, &self.amount)),
+            "second_env" => Some(M::build(id, &self.second_env)),
+            "length2" => Some(M::build(id, &self.length2)),
+            "release_pow2" => Some(M::build(id, &self.release_pow2)),
+            "amount2" => Some(M::build(id, &self.amount2)),
+            "sync_mode" => Some(M::build(id, &self.sync_mode)),
+            "anchor" => Some(M::build(id, &self.anchor)),
+            "rate_hz" => Some(M::build(id, &self.rate_hz)),
+            "period_ms" => Some(M::build(id, &self.period_ms)),
+            "mix" => Some(M::build(id, &self.mix)),
+            "output_gain" => Some(M::build(id, &self.output_gain)),
+            "input_gain" => Some(M::build(id, &self.input_gain)),
+            "width" => Some(M::build(id, &self.width)),
+            "sidechain" => Some(M::build(id, &self.sidechain)),
+            "midi_trigger" => Some(M::build(id, &self.midi_trigger)),
+            "midi_amount" => Some(M::build(id, &self.midi_amount)),
+            "amount_cc" => Some(M::build(id, &self.amount_cc)),
+            "shape" => Some(M::build(id, &self.shape)),
+            "phase_offset" => Some(M::build(id, &self.phase_offset)),
+            "swing" => Some(M::build(id, &self.swing)),
+            "division" => Some(M::build(id, &self.division)),
+            "modifier" => Some(M::build(id, &self.modifier)),
+            "retrigger_on_play" => Some(M::build(id, &self.retrigger_on_play)),
+            "freeze_when_stopped" => Some(M::build(id, &self.freeze_when_stopped)),
+            "smooth_length_change" => Some(M::build(id, &self.smooth_length_change)),
+            "stereo_offset" => Some(M::build(id, &self.stereo_offset)),
+            "bypass" => Some(M::build(id, &self.bypass)),
+            "attack_ms" => Some(M::build(id, &self.attack_ms)),
+            "release_ms" => Some(M::build(id, &self.release_ms)),
+            "invert" => Some(M::build(id, &self.invert)),
+            "polarity" => Some(M::build(id, &self.polarity)),
+            "depth_db" => Some(M::build(id, &self.depth_db)),
+            "hold" => Some(M::build(id, &self.hold)),
+            "oversampling" => Some(M::build(id, &self.oversampling)),
+            "quality" => Some(M::build(id, &self.quality)),
+            "lookahead_ms" => Some(M::build(id, &self.lookahead_ms)),
+            "safety_limit" => Some(M::build(id, &self.safety_limit)),
+            "ceiling_db" => Some(M::build(id, &self.ceiling_db)),
+            "dc_filter" => Some(M::build(id, &self.dc_filter)),
+            "band_split" => Some(M::build(id, &self.band_split)),
+            "crossover_hz" => Some(M::build(id, &self.crossover_hz)),
+            "low_amount" => Some(M::build(id, &self.low_amount)),
+            "high_amount" => Some(M::build(id, &self.high_amount)),
+            "gain_match" => Some(M::build(id, &self.gain_match)),
+            "cc_output" => Some(M::build(id, &self.cc_output)),
+            "cc_number" => Some(M::build(id, &self.cc_number)),
+            "floor_db" => Some(M::build(id, &self.floor_db)),
+            "attack_pow" => Some(M::build(id, &self.attack_pow)),
+            "rms_window_ms" => Some(M::build(id, &self.rms_window_ms)),
+            "peak_hold_ms" => Some(M::build(id, &self.peak_hold_ms)),
+            "mode" => Some(M::build(id, &self.mode)),
+            "threshold_db" => Some(M::build(id, &self.threshold_db)),
+            "tremolo_depth_db" => Some(M::build(id, &self.tremolo_depth_db)),
+            "tremolo_shape" => Some(M::build(id, &self.tremolo_shape)),
+            "intensity" => Some(M::build(id, &self.intensity)),
+            "listen" => Some(M::build(id, &self.listen)),
+            "test_tone" => Some(M::build(id, &self.test_tone)),
+            "tone_hz" => Some(M::build(id, &self.tone_hz)),
+            "stereo_link" => Some(M::build(id, &self.stereo_link)),
+            "emit_key" => Some(M::build(id, &self.emit_key)),
+            "tempo_mult" => Some(M::build(id, &self.tempo_mult)),
+            "bias" => Some(M::build(id, &self.bias)),
+            "custom" => Some(M::build(id, &self.custom)),
+            _ => None,
+        }
+    }
+
+    /// Samples the tempo synced envelope's gain change in decibels at `num_points` evenly spaced
+    /// points across a single cycle. Bars last as long as they do in `time_signature`.
+    fn envelope_curve(&self, num_points: usize, time_signature: TimeSignature) -> Vec<f32> {
+        let step_beats = self.division.value().beats(time_signature) as f32
+            * self.modifier.value().scale() as f32;
+        let cycle_beats = self.length.value() as f32 * step_beats;
+        let second_cycle_beats = self.length2.value() as f32 * step_beats;
+        let envelope = DuckEnvelope {
+            shape: self.shape.value(),
+            custom_curve: self.custom.value().then_some(&self.custom_curve_table),
+            attack_pow: self.attack_pow.value(),
+            release_pow: self.release_pow.value(),
+            amount: self.amount.value(),
+            depth_db: self.depth_db.value(),
+            hold: self.hold.value(),
+            bias: self.bias.value(),
+        };
+        let second_envelope = self.second_env.value().then_some(DuckEnvelope {
+            release_pow: self.release_pow2.value(),
+            amount: self.amount2.value(),
+            ..envelope
+        });
+        let floor_db = self.floor_db.value();
+        let invert = self.invert.value();
+
+        (0..num_points)
+            .map(|i| {
+                if cycle_beats > 0.0 {
+                    let phase = i as f32 / num_points as f32;
+                    let mut gain_db = compute_duck_db(&envelope, phase, cycle_beats);
+                    if let Some(second_envelope) = &second_envelope {
+                        let second_phase =
+                            second_cycle_phase(phase, cycle_beats, second_cycle_beats);
+                        let second_db =
+                            compute_duck_db(second_envelope, second_phase, second_cycle_beats);
+                        gain_db = cascade_duck_db(gain_db, second_db);
+                    }
+                    limit_duck_db(gain_db, floor_db, invert)
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    /// Advances all smoothers by `num_samples` without using their values. This keeps them in
+    /// sync with the parameters while bypassed so they don't jump when the bypass is disengaged.
+    fn skip_smoothers(&self, num_samples: usize) {
+        for _ in 0..num_samples {
+            self.length.smoothed.next();
+            self.attack_pow.smoothed.next();
+            self.release_pow2.smoothed.next();
+            self.amount2.smoothed.next();
+            self.hold.smoothed.next();
+            self.bias.smoothed.next();
+            self.floor_db.smoothed.next();
+            self.rate_hz.smoothed.next();
+            self.period_ms.smoothed.next();
+            self.mix.smoothed.next();
+            self.output_gain.smoothed.next();
+            self.input_gain.smoothed.next();
+            self.width.smoothed.next();
+            self.low_amount.smoothed.next();
+            self.high_amount.smoothed.next();
+            self.ceiling_db.smoothed.next();
+            self.phase_offset.smoothed.next();
+            self.swing.smoothed.next();
+            self.stereo_offset.smoothed.next();
+            self.threshold_db.smoothed.next();
+            self.tremolo_depth_db.smoothed.next();
+            self.tone_hz.smoothed.next();
+        }
+    }
+
+    /// Snaps all smoothers to their parameters' current values, so playback doesn't start with a
+    /// ramp from whatever value the smoothers were at before.
+    fn reset_smoothers(&self) {
+        self.length.smoothed.reset(self.length.value());
+        self.attack_pow.smoothed.reset(self.attack_pow.value());
+        self.release_pow2.smoothed.reset(self.release_pow2.value());
+        self.amount2.smoothed.reset(self.amount2.value());
+        self.hold.smoothed.reset(self.hold.value());
+        self.bias.smoothed.reset(self.bias.value());
+        self.floor_db.smoothed.reset(self.floor_db.value());
+        self.rate_hz.smoothed.reset(self.rate_hz.value());
+        self.period_ms.smoothed.reset(self.period_ms.value());
+        self.mix.smoothed.reset(self.mix.value());
+        self.output_gain.smoothed.reset(self.output_gain.value());
+        self.input_gain.smoothed.reset(self.input_gain.value());
+        self.width.smoothed.reset(self.width.value());
+        self.low_amount.smoothed.reset(self.low_amount.value());
+        self.high_amount.smoothed.reset(self.high_amount.value());
+        self.ceiling_db.smoothed.reset(self.ceiling_db.value());
+        self.phase_offset.smoothed.reset(self.phase_offset.value());
+        self.swing.smoothed.reset(self.swing.value());
+        self.stereo_offset
+            .smoothed
+            .reset(self.stereo_offset.value());
+        self.threshold_db.smoothed.reset(self.threshold_db.value());
+        self.tremolo_depth_db
+            .smoothed
+            .reset(self.tremolo_depth_db.value());
+        self.tone_hz.smoothed.reset(self.tone_hz.value());
+    }
+}
+
+impl Default for GainParams {
+    fn default() -> Self {
+        let gain_value_changed = Arc::new(AtomicBool::new(false));
+        let smoothing_ms_value_changed = Arc::new(AtomicBool::new(false));
+        let smooth_style_value_changed = Arc::new(AtomicBool::new(false));
+        let length_value_changed = Arc::new(AtomicBool::new(false));
+        let release_pow_value_changed = Arc::new(AtomicBool::new(false));
+        let amount_value_changed = Arc::new(AtomicBool::new(false));
+        let second_env_value_changed = Arc::new(AtomicBool::new(false));
+        let length2_value_changed = Arc::new(AtomicBool::new(false));
+        let release_pow2_value_changed = Arc::new(AtomicBool::new(false));
+        let amount2_value_changed = Arc::new(AtomicBool::new(false));
+        let sync_mode_value_changed = Arc::new(AtomicBool::new(false));
+        let anchor_value_changed = Arc::new(AtomicBool::new(false));
+        let rate_hz_value_changed = Arc::new(AtomicBool::new(false));
+        let period_ms_value_changed = Arc::new(AtomicBool::new(false));
+        let mix_value_changed = Arc::new(AtomicBool::new(false));
+        let output_gain_value_changed = Arc::new(AtomicBool::new(false));
+        let input_gain_value_changed = Arc::new(AtomicBool::new(false));
+        let width_value_changed = Arc::new(AtomicBool::new(false));
+        let sidechain_value_changed = Arc::new(AtomicBool::new(false));
+        let midi_trigger_value_changed = Arc::new(AtomicBool::new(false));
+        let midi_amount_value_changed = Arc::new(AtomicBool::new(false));
+        let amount_cc_value_changed = Arc::new(AtomicBool::new(false));
+        let shape_value_changed = Arc::new(AtomicBool::new(false));
+        let phase_offset_value_changed = Arc::new(AtomicBool::new(false));
+        let swing_value_changed = Arc::new(AtomicBool::new(false));
+        let division_value_changed = Arc::new(AtomicBool::new(false));
+        let modifier_value_changed = Arc::new(AtomicBool::new(false));
+        let retrigger_on_play_value_changed = Arc::new(AtomicBool::new(false));
+        let freeze_when_stopped_value_changed = Arc::new(AtomicBool::new(false));
+        let smooth_length_change_value_changed = Arc::new(AtomicBool::new(false));
+        let stereo_offset_value_changed = Arc::new(AtomicBool::new(false));
+        let bypass_value_changed = Arc::new(AtomicBool::new(false));
+        let attack_ms_value_changed = Arc::new(AtomicBool::new(false));
+        let release_ms_value_changed = Arc::new(AtomicBool::new(false));
+        let invert_value_changed = Arc::new(AtomicBool::new(false));
+        let polarity_value_changed = Arc::new(AtomicBool::new(false));
+        let depth_db_value_changed = Arc::new(AtomicBool::new(false));
+        let hold_value_changed = Arc::new(AtomicBool::new(false));
+        let oversampling_value_changed = Arc::new(AtomicBool::new(false));
+        let quality_value_changed = Arc::new(AtomicBool::new(false));
+        let lookahead_ms_value_changed = Arc::new(AtomicBool::new(false));
+        let safety_limit_value_changed = Arc::new(AtomicBool::new(false));
+        let ceiling_db_value_changed = Arc::new(AtomicBool::new(false));
+        let dc_filter_value_changed = Arc::new(AtomicBool::new(false));
+        let band_split_value_changed = Arc::new(AtomicBool::new(false));
+        let crossover_hz_value_changed = Arc::new(AtomicBool::new(false));
+        let low_amount_value_changed = Arc::new(AtomicBool::new(false));
+        let high_amount_value_changed = Arc::new(AtomicBool::new(false));
+        let gain_match_value_changed = Arc::new(AtomicBool::new(false));
+        let cc_output_value_changed = Arc::new(AtomicBool::new(false));
+        let cc_number_value_changed = Arc::new(AtomicBool::new(false));
+        let floor_db_value_changed = Arc::new(AtomicBool::new(false));
+        let attack_pow_value_changed = Arc::new(AtomicBool::new(false));
+        let rms_window_ms_value_changed = Arc::new(AtomicBool::new(false));
+        let peak_hold_ms_value_changed = Arc::new(AtomicBool::new(false));
+        let mode_value_changed = Arc::new(AtomicBool::new(false));
+        let threshold_db_value_changed = Arc::new(AtomicBool::new(false));
+        let tremolo_depth_db_value_changed = Arc::new(AtomicBool::new(false));
+        let tremolo_shape_value_changed = Arc::new(AtomicBool::new(false));
+        let intensity_value_changed = Arc::new(AtomicBool::new(false));
+        let listen_value_changed = Arc::new(AtomicBool::new(false));
+        let test_tone_value_changed = Arc::new(AtomicBool::new(false));
+        let tone_hz_value_changed = Arc::new(AtomicBool::new(false));
+        let stereo_link_value_changed = Arc::new(AtomicBool::new(false));
+        let emit_key_value_changed = Arc::new(AtomicBool::new(false));
+        let tempo_mult_value_changed = Arc::new(AtomicBool::new(false));
+        let bias_value_changed = Arc::new(AtomicBool::new(false));
+        let custom_value_changed = Arc::new(AtomicBool::new(false));
+
+        Self {
+            gain: FloatParam::new(
+                "Gain",
+                util::db_to_gain(0.0),
+                FloatRange::Skewed {
+                    min: util::db_to_gain(-30.0),
+                    max: util::db_to_gain(30.0),
+                    factor: FloatRange::gain_skew_factor(-30.0, 30.0),
+                },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db())
+            .with_callback(change_flag_callback(&gain_value_changed)),
+            gain_value_changed,
+
+            smoothing_ms: FloatParam::new(
+                "Smoothing",
+                50.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 1000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(0))
+            .with_callback(change_flag_callback(&smoothing_ms_value_changed)),
+            smoothing_ms_value_changed,
+
+            smooth_style: EnumParam::new("Smooth Style", SmoothStyle::Linear)
+                .with_callback(change_flag_callback(&smooth_style_value_changed)),
+            smooth_style_value_changed,
+
+            release_pow: FloatParam::new(
+                "Release Pow",
+                10.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 20.0,
+                },
+            )
+            .with_callback(change_flag_callback(&release_pow_value_changed)),
+            release_pow_value_changed,
+
+            // The integer smoother rounds its interpolated value, so when fading in from a length
+            // of 0 the effect only turns on halfway through the smoothing period instead of
+            // ramping up from some fractional length
+            length: IntParam::new("Lenght", 0, IntRange::Linear { min: 0, max: 4 })
+                .with_smoother(SmoothingStyle::Linear(20.0))
+                .with_value_to_string(v2s_length_steps())
+                .with_string_to_value(s2v_length_steps())
+                .with_callback(change_flag_callback(&length_value_changed)),
+            length_value_changed,
+
+            amount: FloatParam::new("Amount", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_callback(change_flag_callback(&amount_value_changed)),
+            amount_value_changed,
+
+            second_env: BoolParam::new("Second Envelope", false)
+                .with_callback(change_flag_callback(&second_env_value_changed)),
+            second_env_value_changed,
+
+            // Defaults to three against the first envelope's four
+            length2: IntParam::new("Length 2", 3, IntRange::Linear { min: 1, max: 8 })
+                .with_callback(change_flag_callback(&length2_value_changed)),
+            length2_value_changed,
+
+            release_pow2: FloatParam::new(
+                "Release Pow 2",
+                10.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 20.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_callback(change_flag_callback(&release_pow2_value_changed)),
+            release_pow2_value_changed,
+
+            amount2: FloatParam::new("Amount 2", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_smoother(SmoothingStyle::Linear(50.0))
+                .with_callback(change_flag_callback(&amount2_value_changed)),
+            amount2_value_changed,
+
+            sync_mode: EnumParam::new("Sync Mode", SyncMode::HostTempo)
+                .with_callback(change_flag_callback(&sync_mode_value_changed)),
+            sync_mode_value_changed,
+
+            anchor: EnumParam::new("Anchor", Anchor::SongStart)
+                .with_callback(change_flag_callback(&anchor_value_changed)),
+            anchor_value_changed,
+
+            rate_hz: FloatParam::new(
+                "Rate",
+                2.0,
+                FloatRange::Skewed {
+                    min: 0.1,
+                    max: 20.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit(" Hz")
+            .with_value_to_string(formatters::v2s_f32_rounded(2))
+            .with_callback(change_flag_callback(&rate_hz_value_changed)),
+            rate_hz_value_changed,
+
+            period_ms: FloatParam::new(
+                "Period",
+                500.0,
+                FloatRange::Skewed {
+                    min: 10.0,
+                    max: 10_000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(0))
+            .with_callback(change_flag_callback(&period_ms_value_changed)),
+            period_ms_value_changed,
+
+            // Logarithmic smoothing can't reach a fully dry mix of 0, so this uses exponential
+            // smoothing instead
+            mix: FloatParam::new("Mix", 1.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_smoother(SmoothingStyle::Exponential(50.0))
+                .with_unit("%")
+                .with_value_to_string(formatters::v2s_f32_percentage(0))
+                .with_string_to_value(formatters::s2v_f32_percentage())
+                .with_callback(change_flag_callback(&mix_value_changed)),
+            mix_value_changed,
+
+            output_gain: FloatParam::new(
+                "Output Gain",
+                util::db_to_gain(0.0),
+                FloatRange::Skewed {
+                    min: util::db_to_gain(-30.0),
+                    max: util::db_to_gain(30.0),
+                    factor: FloatRange::gain_skew_factor(-30.0, 30.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db())
+            .with_callback(change_flag_callback(&output_gain_value_changed)),
+            output_gain_value_changed,
+
+            input_gain: FloatParam::new(
+                "Input Gain",
+                util::db_to_gain(0.0),
+                FloatRange::Skewed {
+                    min: util::db_to_gain(-30.0),
+                    max: util::db_to_gain(30.0),
+                    factor: FloatRange::gain_skew_factor(-30.0, 30.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db())
+            .with_callback(change_flag_callback(&input_gain_value_changed)),
+            input_gain_value_changed,
+
+            width: FloatParam::new("Width", 1.0, FloatRange::Linear { min: 0.0, max: 2.0 })
+                .with_smoother(SmoothingStyle::Linear(50.0))
+                .with_unit("%")
+                .with_value_to_string(formatters::v2s_f32_percentage(0))
+                .with_string_to_value(formatters::s2v_f32_percentage())
+                .with_callback(change_flag_callback(&width_value_changed)),
+            width_value_changed,
+
+            sidechain: BoolParam::new("Sidechain", false)
+                .with_callback(change_flag_callback(&sidechain_value_changed)),
+            sidechain_value_changed,
+
+            midi_trigger: BoolParam::new("MIDI Trigger", false)
+                .with_callback(change_flag_callback(&midi_trigger_value_changed)),
+            midi_trigger_value_changed,
+
+            midi_amount: BoolParam::new("MIDI Amount", false)
+                .with_callback(change_flag_callback(&midi_amount_value_changed)),
+            midi_amount_value_changed,
+
+            // Defaults to the mod wheel
+            amount_cc: IntParam::new("Amount CC", 1, IntRange::Linear { min: 0, max: 119 })
+                .with_callback(change_flag_callback(&amount_cc_value_changed)),
+            amount_cc_value_changed,
+
+            shape: EnumParam::new("Shape", EnvShape::Exponential)
+                .with_callback(change_flag_callback(&shape_value_changed)),
+            shape_value_changed,
+
+            phase_offset: FloatParam::new(
+                "Phase Offset",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage())
+            .with_callback(change_flag_callback(&phase_offset_value_changed)),
+            phase_offset_value_changed,
+
+            swing: FloatParam::new(
+                "Swing",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 0.75,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage())
+            .with_callback(change_flag_callback(&swing_value_changed)),
+            swing_value_changed,
+
+            division: EnumParam::new("Division", Division::Quarter)
+                .with_callback(change_flag_callback(&division_value_changed)),
+            division_value_changed,
+
+            modifier: EnumParam::new("Modifier", Modifier::Straight)
+                .with_callback(change_flag_callback(&modifier_value_changed)),
+            modifier_value_changed,
+
+            retrigger_on_play: BoolParam::new("Retrigger On Play", false)
+                .with_callback(change_flag_callback(&retrigger_on_play_value_changed)),
+            retrigger_on_play_value_changed,
+
+            freeze_when_stopped: BoolParam::new("Freeze When Stopped", false)
+                .with_callback(change_flag_callback(&freeze_when_stopped_value_changed)),
+            freeze_when_stopped_value_changed,
+
+            smooth_length_change: BoolParam::new("Smooth Length Change", false)
+                .with_callback(change_flag_callback(&smooth_length_change_value_changed)),
+            smooth_length_change_value_changed,
+
+            stereo_offset: FloatParam::new(
+                "Stereo Offset",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 0.5 },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage())
+            .with_callback(change_flag_callback(&stereo_offset_value_changed)),
+            stereo_offset_value_changed,
+
+            bypass: BoolParam::new("Bypass", false)
+                .with_value_to_string(formatters::v2s_bool_bypass())
+                .with_string_to_value(formatters::s2v_bool_bypass())
+                .with_callback(change_flag_callback(&bypass_value_changed))
+                .make_bypass(),
+            bypass_value_changed,
+
+            attack_ms: FloatParam::new(
+                "Attack",
+                1.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 200.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1))
+            .with_callback(change_flag_callback(&attack_ms_value_changed)),
+            attack_ms_value_changed,
+
+            release_ms: FloatParam::new(
+                "Release",
+                5.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 500.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1))
+            .with_callback(change_flag_callback(&release_ms_value_changed)),
+            release_ms_value_changed,
+
+            invert: BoolParam::new("Invert", false)
+                .with_callback(change_flag_callback(&invert_value_changed)),
+            invert_value_changed,
+
+            polarity: BoolParam::new("Invert Polarity", false)
+                .with_callback(change_flag_callback(&polarity_value_changed)),
+            polarity_value_changed,
+
+            depth_db: FloatParam::new(
+                "Depth",
+                50.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 60.0,
+                },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1))
+            .with_callback(change_flag_callback(&depth_db_value_changed)),
+            depth_db_value_changed,
+
+            hold: FloatParam::new("Hold", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_smoother(SmoothingStyle::Linear(50.0))
+                .with_unit("%")
+                .with_value_to_string(formatters::v2s_f32_percentage(0))
+                .with_string_to_value(formatters::s2v_f32_percentage())
+                .with_callback(change_flag_callback(&hold_value_changed)),
+            hold_value_changed,
+
+            oversampling: EnumParam::new("Oversampling", Oversampling::Off)
+                .with_callback(change_flag_callback(&oversampling_value_changed)),
+            oversampling_value_changed,
+
+            quality: EnumParam::new("Quality", Quality::High)
+                .with_callback(change_flag_callback(&quality_value_changed)),
+            quality_value_changed,
+
+            lookahead_ms: FloatParam::new(
+                "Lookahead",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: MAX_LOOKAHEAD_MS,
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1))
+            .with_callback(change_flag_callback(&lookahead_ms_value_changed)),
+            lookahead_ms_value_changed,
+
+            safety_limit: BoolParam::new("Safety Limit", false)
+                .with_callback(change_flag_callback(&safety_limit_value_changed)),
+            safety_limit_value_changed,
+
+            ceiling_db: FloatParam::new(
+                "Ceiling",
+                0.0,
+                FloatRange::Linear {
+                    min: -24.0,
+                    max: 0.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1))
+            .with_callback(change_flag_callback(&ceiling_db_value_changed)),
+            ceiling_db_value_changed,
+
+            dc_filter: BoolParam::new("DC Filter", false)
+                .with_callback(change_flag_callback(&dc_filter_value_changed)),
+            dc_filter_value_changed,
+
+            band_split: BoolParam::new("Split Bands", false)
+                .with_callback(change_flag_callback(&band_split_value_changed)),
+            band_split_value_changed,
+
+            crossover_hz: FloatParam::new(
+                "Crossover",
+                200.0,
+                FloatRange::Skewed {
+                    min: 20.0,
+                    max: 20000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" Hz")
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(0))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz())
+            .with_callback(change_flag_callback(&crossover_hz_value_changed)),
+            crossover_hz_value_changed,
+
+            low_amount: FloatParam::new(
+                "Low Amount",
+                1.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage())
+            .with_callback(change_flag_callback(&low_amount_value_changed)),
+            low_amount_value_changed,
+
+            high_amount: FloatParam::new(
+                "High Amount",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage())
+            .with_callback(change_flag_callback(&high_amount_value_changed)),
+            high_amount_value_changed,
+
+            gain_match: BoolParam::new("Gain Match", false)
+                .with_callback(change_flag_callback(&gain_match_value_changed)),
+            gain_match_value_changed,
+
+            cc_output: BoolParam::new("CC Output", false)
+                .with_callback(change_flag_callback(&cc_output_value_changed)),
+            cc_output_value_changed,
+
+            // CCs 120 and up are channel mode messages
+            cc_number: IntParam::new("CC Number", 20, IntRange::Linear { min: 0, max: 119 })
+                .with_callback(change_flag_callback(&cc_number_value_changed)),
+            cc_number_value_changed,
+
+            floor_db: FloatParam::new(
+                "Floor",
+                -60.0,
+                FloatRange::Linear {
+                    min: -60.0,
+                    max: 0.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1))
+            .with_callback(change_flag_callback(&floor_db_value_changed)),
+            floor_db_value_changed,
+
+            attack_pow: FloatParam::new(
+                "Attack Pow",
+                10.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 20.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_callback(change_flag_callback(&attack_pow_value_changed)),
+            attack_pow_value_changed,
+
+            rms_window_ms: FloatParam::new(
+                "RMS Window",
+                300.0,
+                FloatRange::Skewed {
+                    min: 10.0,
+                    max: MAX_RMS_WINDOW_MS,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(0))
+            .with_callback(change_flag_callback(&rms_window_ms_value_changed)),
+            rms_window_ms_value_changed,
+
+            peak_hold_ms: FloatParam::new(
+                "Peak Hold",
+                1000.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 5000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(0))
+            .with_callback(change_flag_callback(&peak_hold_ms_value_changed)),
+            peak_hold_ms_value_changed,
+
+            mode: EnumParam::new("Mode", Mode::Duck)
+                .with_callback(change_flag_callback(&mode_value_changed)),
+            mode_value_changed,
+
+            threshold_db: FloatParam::new(
+                "Threshold",
+                -40.0,
+                FloatRange::Linear {
+                    min: -80.0,
+                    max: 0.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1))
+            .with_callback(change_flag_callback(&threshold_db_value_changed)),
+            threshold_db_value_changed,
+
+            tremolo_depth_db: FloatParam::new(
+                "Tremolo Depth",
+                6.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 24.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(50.0))
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1))
+            .with_callback(change_flag_callback(&tremolo_depth_db_value_changed)),
+            tremolo_depth_db_value_changed,
+
+            tremolo_shape: EnumParam::new("Tremolo Shape", EnvShape::Sine)
+                .with_callback(change_flag_callback(&tremolo_shape_value_changed)),
+            tremolo_shape_value_changed,
+
+            intensity: FloatParam::new("Intensity", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_unit("%")
+                .with_value_to_string(formatters::v2s_f32_percentage(0))
+                .with_string_to_value(formatters::s2v_f32_percentage())
+                .with_callback(change_flag_callback(&intensity_value_changed)),
+            intensity_value_changed,
+
+            listen: EnumParam::new("Listen", Listen::Normal)
+                .with_callback(change_flag_callback(&listen_value_changed)),
+            listen_value_changed,
+
+            test_tone: BoolParam::new("Test Tone", false)
+                .with_callback(change_flag_callback(&test_tone_value_changed)),
+            test_tone_value_changed,
+
+            tone_hz: FloatParam::new(
+                "Tone Frequency",
+                110.0,
+                FloatRange::Skewed {
+                    min: 20.0,
+                    max: 2000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(20.0))
+            .with_unit(" Hz")
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(0))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz())
+            .with_callback(change_flag_callback(&tone_hz_value_changed)),
+            tone_hz_value_changed,
+
+            stereo_link: BoolParam::new("Stereo Link", true)
+                .with_callback(change_flag_callback(&stereo_link_value_changed)),
+            stereo_link_value_changed,
+
+            emit_key: BoolParam::new("Emit Key", false)
+                .with_callback(change_flag_callback(&emit_key_value_changed)),
+            emit_key_value_changed,
+
+            tempo_mult: EnumParam::new("Tempo Multiplier", TempoMult::Normal)
+                .with_callback(change_flag_callback(&tempo_mult_value_changed)),
+            tempo_mult_value_changed,
+
+            bias: FloatParam::new("Bias", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_smoother(SmoothingStyle::Linear(50.0))
+                .with_unit("%")
+                .with_value_to_string(formatters::v2s_f32_percentage(0))
+                .with_string_to_value(formatters::s2v_f32_percentage())
+                .with_callback(change_flag_callback(&bias_value_changed)),
+            bias_value_changed,
+
+            custom: BoolParam::new("Custom Curve", false)
+                .with_callback(change_flag_callback(&custom_value_changed)),
+            custom_value_changed,
+
+            custom_curve: Arc::new(RwLock::new(CustomCurve::default().points())),
+            custom_curve_table: CustomCurve::default(),
+
+            editor_size: Arc::new(RwLock::new(DEFAULT_EDITOR_SIZE)),
+            ui_scale: Arc::new(RwLock::new(1.0)),
+            bg_color: Arc::new(RwLock::new(DEFAULT_BACKGROUND_COLOR)),
+            state_version: Arc::new(RwLock::new(STATE_VERSION)),
+        }
+    }
+}
+
+impl Plugin for SoutGainRs {
+    type BackgroundTask = ();
+    type SysExMessage = ();
+
+    const NAME: &'static str = "SoutExGain";
+    const VENDOR: &'static str = "sout";
+    const URL: &'static str = env!("CARGO_PKG_HOMEPAGE");
+    const EMAIL: &'static str = "sout_nantang@outlook.com";
+
+    const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+
+    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(2),
+            main_output_channels: NonZeroU32::new(2),
+            aux_input_ports: &[new_nonzero_u32(2)],
+            aux_output_ports: &[new_nonzero_u32(2)],
+            names: PortNames {
+                aux_inputs: &["Sidechain"],
+                aux_outputs: &["Key"],
+                ..PortNames::const_default()
+            },
+        },
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(1),
+            main_output_channels: NonZeroU32::new(1),
+            ..AudioIOLayout::const_default()
+        },
+        // The mono input is copied to both output channels, so the stereo offset still applies
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(1),
+            main_output_channels: NonZeroU32::new(2),
+            aux_input_ports: &[new_nonzero_u32(2)],
+            aux_output_ports: &[new_nonzero_u32(2)],
+            names: PortNames {
+                aux_inputs: &["Sidechain"],
+                aux_outputs: &["Key"],
+                ..PortNames::const_default()
+            },
+        },
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(4),
+            main_output_channels: NonZeroU32::new(4),
+            aux_input_ports: &[new_nonzero_u32(2)],
+            aux_output_ports: &[new_nonzero_u32(2)],
+            names: PortNames {
+                aux_inputs: &["Sidechain"],
+                aux_outputs: &["Key"],
+                ..PortNames::const_default()
+            },
+        },
+    ];
+
+    // Note events retrigger the cycle, and a CC can control the amount. Syncing to MIDI clock
+    // isn't possible here: nih-plug drops MIDI real-time messages (clock, start, stop, and
+    // continue) before they reach the plugin, and VST3 doesn't carry them at all. The cycle
+    // follows the host's transport instead, which hosts keep in sync with an external clock
+    // themselves.
+    const MIDI_INPUT: MidiConfig = MidiConfig::MidiCCs;
+    // nih-plug can't expose a plugin's output as a CLAP modulation source, so the gain reduction
+    // is sent as a MIDI CC instead
+    const MIDI_OUTPUT: MidiConfig = MidiConfig::MidiCCs;
+    const SAMPLE_ACCURATE_AUTOMATION: bool = true;
+
+    fn params(&self) -> Arc<dyn Params> {
+        self.params.clone()
+    }
+
+    fn filter_state(state: &mut PluginState) {
+        migrate_state(state);
+    }
+
+    fn initialize(
+        &mut self,
+        audio_io_layout: &AudioIOLayout,
+        buffer_config: &BufferConfig,
+        context: &mut impl InitContext<Self>,
+    ) -> bool {
+        self.sample_rate = buffer_config.sample_rate;
+        // Makes it easy to tell apart forks that accidentally share this plugin's IDs
+        nih_log!(
+            "Initializing {} (CLAP ID {}, VST3 class ID {:?})",
+            Self::NAME,
+            Self::CLAP_ID,
+            String::from_utf8_lossy(&Self::VST3_CLASS_ID)
+        );
+        // The persisted curve may have been restored since the last time the plugin was
+        // initialized
+        self.params
+            .custom_curve_table
+            .set(&self.params.custom_curve.read().unwrap());
+        let num_channels = audio_io_layout
+            .main_output_channels
+            .map(NonZeroU32::get)
+            .unwrap_or(0) as usize;
+        self.upmix_mono =
+            audio_io_layout.main_input_channels.map(NonZeroU32::get) == Some(1) && num_channels > 1;
+        self.allocate(num_channels);
+        // The lookahead is specified in milliseconds, so the latency needs to be recomputed
+        // whenever the sample rate changes
+        self.latency_samples = self.total_latency_samples();
+        context.set_latency_samples(self.latency_samples);
+        self.meters.sample_rate.store(self.sample_rate);
+        self.meters
+            .latency_samples
+            .store(self.latency_samples, Ordering::Relaxed);
+
+        true
+    }
+
+    /// Called by the host when playback restarts, e.g. when looping a section. Any state left over
+    /// from before the restart would otherwise be heard as a glitch at the start of the loop.
+    fn reset(&mut self) {
+        self.params.reset_smoothers();
+        self.gain_smoother.reset(self.params.gain.value());
+        self.reset_envelope_smoothers();
+        self.cc_amount_base = None;
+        self.fallback_seconds = 0.0;
+        self.accumulated_beat = 0.0;
+        self.second_beat = 0.0;
+        self.pair_beat = 0.0;
+        self.beat_synced = false;
+        self.previous_cycle_beats = 0.0;
+        self.length_fade = None;
+        self.sidechain_envelopes.fill(0.0);
+        self.gate_envelopes.fill(0.0);
+        // Unity gain is where the ducking gain rests, so starting from it doesn't fade in from
+        // silence
+        self.duck_gain_state.fill(1.0);
+        for ramp in &mut self.duck_ramps {
+            ramp.reset(1.0);
+        }
+        for oversampler in &mut self.oversamplers {
+            oversampler.reset();
+        }
+        for delay in &mut self.lookahead_delays {
+            delay.reset();
+        }
+        for blocker in &mut self.dc_blockers {
+            blocker.reset();
+        }
+        for crossover in &mut self.crossovers {
+            crossover.reset();
+        }
+        for delay in &mut self.band_delays {
+            delay.reset();
+        }
+        for gain_match in &mut self.gain_matches {
+            gain_match.reset();
+        }
+        self.last_cc_value = None;
+        // The host always resets the plugin after initializing it, so this also covers inserting
+        // the plugin during playback
+        self.soft_start_remaining = self.soft_start_samples;
+        self.tone_phase = 0.0;
+
+        self.input_rms.reset();
+        self.output_rms.reset();
+        self.correlation.reset();
+        self.scope_frame = [0.0; SCOPE_POINTS];
+        self.scope_point = 0;
+        self.scope_sample = 0;
+        self.meters.gain_reduction_db.store(0.0);
+        self.meters.pre_trim_peak.store(0.0);
+        self.meters.input_peak.store(0.0);
+        self.meters.output_peak.store(0.0);
+        self.meters.input_channel_peaks.reset();
+        self.meters.output_channel_peaks.reset();
+        self.input_hold.reset();
+        self.output_hold.reset();
+        self.meters.input_hold.store(0.0);
+        self.meters.output_hold.store(0.0);
+        self.meters.input_rms.store(0.0);
+        self.meters.output_rms.store(0.0);
+        self.meters.correlation.store(f32::NAN);
+        self.meters.beat_phase.store(0.0);
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        aux: &mut AuxiliaryBuffers,
+        context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        let transport = context.transport();
+        if let Some(tempo) = transport.tempo {
+            self.tempo = tempo;
+        }
+        self.meters.tempo.store(self.tempo as f32);
+        // Hosts that don't report a time signature keep using the last one they did report
+        if let (Some(numerator), Some(denominator)) =
+            (transport.time_sig_numerator, transport.time_sig_denominator)
+        {
+            if numerator > 0 && denominator > 0 {
+                self.time_signature = TimeSignature {
+                    numerator: numerator as u32,
+                    denominator: denominator as u32,
+                };
+            }
+        }
+        self.meters
+            .time_sig_numerator
+            .store(self.time_signature.numerator, Ordering::Relaxed);
+        self.meters
+            .time_sig_denominator
+            .store(self.time_signature.denominator, Ordering::Relaxed);
+        self.meters
+            .tempo_available
+            .store(transport.tempo.is_some(), Ordering::Relaxed);
+        let block_start_seconds = transport.pos_seconds().unwrap_or(self.fallback_seconds);
+        // The host's position in quarter notes already follows its tempo map, so it's used
+        // instead of the position in seconds whenever the host provides it. The tempo multiplier
+        // scales the beats the same way it scales the tempo.
+        let block_start_beats = transport
+            .pos_beats()
+            .map(|beats| beats * self.params.tempo_mult.value().factor());
+        let retrigger_on_play = self.params.retrigger_on_play.value();
+        let anchor = self.params.anchor.value();
+        let sample_duration = 1.0 / self.sample_rate as f64;
+        let playing = transport.playing;
+        let started_playing = playing && !self.was_playing;
+        // A loop wrapping around shows up as the transport jumping backwards during playback
+        let looped = playing
+            && self.was_playing
+            && block_start_seconds < self.fallback_seconds - sample_duration;
+        if (started_playing && (retrigger_on_play || anchor == Anchor::LoopStart))
+            || (looped && anchor == Anchor::LoopStart)
+        {
+            self.accumulated_beat = 0.0;
+            self.second_beat = 0.0;
+            self.pair_beat = 0.0;
+        }
+        self.was_playing = playing;
+        self.meters.playing.store(playing, Ordering::Relaxed);
+        // Seeking, looping, and starting playback all move the transport somewhere other than
+        // where the previous block ended
+        if (block_start_seconds - self.fallback_seconds).abs() > sample_duration {
+            self.beat_synced = false;
+        }
+
+        let oversampling = self.params.oversampling.value();
+        if oversampling != self.oversampling {
+            self.oversampling = oversampling;
+            for oversampler in &mut self.oversamplers {
+                oversampler.reset();
+            }
+        }
+        if self.upmix_mono {
+            if let [input, outputs @ ..] = buffer.as_slice() {
+                for output in outputs {
+                    output.copy_from_slice(input);
+                }
+            }
+        }
+
+        let lookahead_samples = self.lookahead_samples();
+        let latency_samples = self.total_latency_samples();
+        if latency_samples != self.latency_samples {
+            self.latency_samples = latency_samples;
+            context.set_latency_samples(latency_samples);
+            self.meters
+                .latency_samples
+                .store(latency_samples, Ordering::Relaxed);
+        }
+
+        // Hosts that don't support auxiliary outputs, or don't connect this one, don't provide a
+        // buffer for it. The key is silent unless it's enabled and the plugin isn't bypassed.
+        let mut key_output = aux.outputs.first_mut().map(|buffer| buffer.as_slice());
+        for channel in key_output
+            .iter_mut()
+            .flat_map(|channels| channels.iter_mut())
+        {
+            channel.fill(0.0);
+        }
+
+        if self.params.bypass.value() {
+            // The signal is still delayed by the reported latency so it stays in sync with the
+            // other tracks
+            for channel_samples in buffer.iter_samples() {
+                for ((oversampler, delay), sample) in self
+                    .oversamplers
+                    .iter_mut()
+                    .zip(&mut self.lookahead_delays)
+                    .zip(channel_samples)
+                {
+                    let delayed = delay.process(*sample, lookahead_samples);
+                    *sample = oversampler.delay_dry(oversampling, delayed);
+                }
+            }
+            self.params.skip_smoothers(buffer.samples());
+            self.gain_smoother.reset(self.params.gain.value());
+            self.reset_envelope_smoothers();
+            self.meters.gain_reduction_db.store(0.0);
+            self.fallback_seconds = block_start_seconds + buffer.samples() as f64 * sample_duration;
+
+            return ProcessStatus::Normal;
+        }
+
+        let position = BlockPosition {
+            seconds: block_start_seconds,
+            beats: block_start_beats,
+            playing,
+        };
+        // The host may not connect anything to the sidechain input, in which case the follower
+        // simply never triggers
+        let sidechain_input = aux.inputs.first().map(|buffer| buffer.as_slice_immutable());
+        let block_min_duck_gain = self.process_block(
+            buffer.as_slice(),
+            sidechain_input,
+            key_output,
+            position,
+            || context.next_event(),
+        );
+
+        self.fallback_seconds = block_start_seconds + buffer.samples() as f64 * sample_duration;
+        // The CC goes from 0 at unity gain to 1 when fully ducked, and is only sent once per block
+        // when it has changed by at least one MIDI step
+        if self.params.cc_output.value() {
+            let value = 1.0 - block_min_duck_gain.clamp(0.0, 1.0);
+            let changed = self
+                .last_cc_value
+                .map_or(true, |last| (value - last).abs() >= 1.0 / 127.0);
+            if changed {
+                context.send_event(NoteEvent::MidiCC {
+                    timing: 0,
+                    channel: 0,
+                    cc: self.params.cc_number.value() as u8,
+                    value,
+                });
+                self.last_cc_value = Some(value);
+            }
+        }
+
+        ProcessStatus::Normal
+    }
+
+    /// The web view's developer tools are always available in debug builds. Since the web view has
+    /// to be configured when it's created, release builds only enable them when the
+    /// `DEVTOOLS_ENV_VAR` environment variable is set as the editor gets opened.
+    fn editor(&mut self, _async_executor: AsyncExecutor<Self>) -> Option<Box<dyn Editor>> {
+        let developer_mode = cfg!(debug_assertions)
+            || std::env::var_os(DEVTOOLS_ENV_VAR).is_some_and(|value| !value.is_empty());
+        let params = self.params.clone();
+        let meters = self.meters.clone();
+        let snapshots = self.snapshots.clone();
+        let last_sent_reduction_db = AtomicF32::new(0.0);
+        let last_levels_sent = Mutex::new(Instant::now());
+        let last_transport_sent = Mutex::new(Instant::now());
+        let scope_frames_read = AtomicUsize::new(0);
+        // The web UI assumes the host reports a tempo until it's told otherwise
+        let last_sent_tempo_available = AtomicBool::new(true);
+        // Whether the web UI has been told about the current clip, so it's only told once
+        let clip_sent = AtomicBool::new(false);
+        // The sample rate and latency the web UI was last told about, sent along with the size on
+        // `Init` and again whenever they change
+        let last_sent_sample_rate = AtomicF32::new(0.0);
+        let last_sent_latency_samples = AtomicU32::new(0);
+        // The number of points the web UI last requested for the envelope curve, or zero if it
+        // hasn't requested the curve yet
+        let curve_points = AtomicUsize::new(0);
+        // The recent taps for `Action::Tap`
+        let tap_tempo = Mutex::new(TapTempo::default());
+        // Every resize, including restoring the persisted size, is clamped to these
+        let size_constraints = Mutex::new(DEFAULT_SIZE_CONSTRAINTS);
+        // The last preset file dropped onto the editor. The preset can only be applied from the
+        // event loop since that's where the `ParamSetter` lives.
+        let dropped_preset: Arc<Mutex<Option<PathBuf>>> = Arc::new(Mutex::new(None));
+        let mouse_dropped_preset = dropped_preset.clone();
+        // The persisted size hasn't been restored yet at this point, so the editor starts out at
+        // the default size and is resized to the persisted size when the web UI sends `Init`
+        let bg_color = *self.params.bg_color.read().unwrap();
+        let editor = WebViewEditor::new(
+            html_source(),
+            (DEFAULT_EDITOR_SIZE.width, DEFAULT_EDITOR_SIZE.height),
+        )
+        .with_background_color((bg_color.r, bg_color.g, bg_color.b, bg_color.a))
+        .with_developer_mode(developer_mode)
+        .with_keyboard_handler(move |event| {
+            println!("keyboard event: {event:#?}");
+            event.key == Key::Escape
+        })
+        .with_mouse_handler(move |event| match event {
+            MouseEvent::DragEntered { .. } => {
+                println!("drag entered");
+                EventStatus::AcceptDrop(DropEffect::Copy)
+            }
+            MouseEvent::DragMoved { .. } => {
+                println!("drag moved");
+                EventStatus::AcceptDrop(DropEffect::Copy)
+            }
+            MouseEvent::DragLeft => {
+                println!("drag left");
+                EventStatus::Ignored
+            }
+            MouseEvent::DragDropped { data, .. } => {
+                if let DropData::Files(files) = data {
+                    println!("drag dropped: {:?}", files);
+                    let preset = files
+                        .into_iter()
+                        .find(|path| path.extension().is_some_and(|ext| ext == "json"));
+                    if preset.is_some() {
+                        *mouse_dropped_preset.lock().unwrap() = preset;
+                    }
+                }
+                EventStatus::AcceptDrop(DropEffect::Copy)
+            }
+            _ => EventStatus::Ignored,
+        })
+        .with_event_loop(move |ctx, setter, window| {
+            let mut constraints_changed = false;
+            while let Ok(value) = ctx.next_event() {
+                match serde_json::from_value::<Action>(value) {
+                    Ok(action) => match action {
+                        Action::SetGain { value } => {
+                            set_normalized_from_ui(&setter, &params.gain, value);
+                        }
+                        Action::SetSmoothing { value } => {
+                            set_normalized_from_ui(&setter, &params.smoothing_ms, value);
+                        }
+                        Action::SetSmoothStyle { value } => {
+                            if (value as usize) < SmoothStyle::variants().len() {
+                                let style = SmoothStyle::from_index(value as usize);
+                                setter.begin_set_parameter(&params.smooth_style);
+                                setter.set_parameter(&params.smooth_style, style);
+                                setter.end_set_parameter(&params.smooth_style);
+                            }
+                        }
+                        Action::SetLength { value } => {
+                            // The normalization clamps the length to the parameter's range
+                            if value.is_finite() {
+                                let normalized = params.length.preview_normalized(value as i32);
+                                set_normalized_from_ui(&setter, &params.length, normalized);
+                            }
+                        }
+                        Action::SetReleasePow { value } => {
+                            set_normalized_from_ui(&setter, &params.release_pow, value);
+                        }
+                        Action::SetAmount { value } => {
+                            set_normalized_from_ui(&setter, &params.amount, value);
+                        }
+                        Action::SetSecondEnv { value } => {
+                            setter.begin_set_parameter(&params.second_env);
+                            setter.set_parameter(&params.second_env, value);
+                            setter.end_set_parameter(&params.second_env);
+                        }
+                        Action::SetLength2 { value } => {
+                            if value.is_finite() {
+                                let normalized = params.length2.preview_normalized(value as i32);
+                                set_normalized_from_ui(&setter, &params.length2, normalized);
+                            }
+                        }
+                        Action::SetPow2 { value } => {
+                            set_normalized_from_ui(&setter, &params.release_pow2, value);
+                        }
+                        Action::SetAmount2 { value } => {
+                            set_normalized_from_ui(&setter, &params.amount2, value);
+                        }
+                        Action::SetMix { value } => {
+                            set_normalized_from_ui(&setter, &params.mix, value);
+                        }
+                        Action::SetOutputGain { value } => {
+                            set_normalized_from_ui(&setter, &params.output_gain, value);
+                        }
+                        Action::SetInputGain { value } => {
+                            set_normalized_from_ui(&setter, &params.input_gain, value);
+                        }
+                        Action::SetWidth { value } => {
+                            set_normalized_from_ui(&setter, &params.width, value);
+                        }
+                        Action::SetSidechain { value } => {
+                            setter.begin_set_parameter(&params.sidechain);
+                            setter.set_parameter(&params.sidechain, value);
+                            setter.end_set_parameter(&params.sidechain);
+                        }
+                        Action::SetMidiTrigger { value } => {
+                            setter.begin_set_parameter(&params.midi_trigger);
+                            setter.set_parameter(&params.midi_trigger, value);
+                            setter.end_set_parameter(&params.midi_trigger);
+                        }
+                        Action::SetMidiAmount { value } => {
+                            setter.begin_set_parameter(&params.midi_amount);
+                            setter.set_parameter(&params.midi_amount, value);
+                            setter.end_set_parameter(&params.midi_amount);
+                        }
+                        Action::SetAmountCc { value } => {
+                            let normalized = params.amount_cc.preview_normalized(value as i32);
+                            set_normalized_from_ui(&setter, &params.amount_cc, normalized);
+                        }
+                        Action::SetShape { value } => {
+                            if (value as usize) < EnvShape::variants().len() {
+                                let shape = EnvShape::from_index(value as usize);
+                                setter.begin_set_parameter(&params.shape);
+                                setter.set_parameter(&params.shape, shape);
+                                setter.end_set_parameter(&params.shape);
+                            }
+                        }
+                        Action::SetSwing { value } => {
+                            set_normalized_from_ui(&setter, &params.swing, value);
+                        }
+                        Action::SetPhase { value } => {
+                            set_normalized_from_ui(&setter, &params.phase_offset, value);
+                        }
+                        Action::SetDivision { value } => {
+                            if (value as usize) < Division::variants().len() {
+                                let division = Division::from_index(value as usize);
+                                setter.begin_set_parameter(&params.division);
+                                setter.set_parameter(&params.division, division);
+                                setter.end_set_parameter(&params.division);
+                            }
+                        }
+                        Action::SetModifier { value } => {
+                            if (value as usize) < Modifier::variants().len() {
+                                let modifier = Modifier::from_index(value as usize);
+                                setter.begin_set_parameter(&params.modifier);
+                                setter.set_parameter(&params.modifier, modifier);
+                                setter.end_set_parameter(&params.modifier);
+                            }
+                        }
+                        Action::SetSmoothLengthChange { value } => {
+                            setter.begin_set_parameter(&params.smooth_length_change);
+                            setter.set_parameter(&params.smooth_length_change, value);
+                            setter.end_set_parameter(&params.smooth_length_change);
+                        }
+                        Action::SetRetriggerOnPlay { value } => {
+                            setter.begin_set_parameter(&params.retrigger_on_play);
+                            setter.set_parameter(&params.retrigger_on_play, value);
+                            setter.end_set_parameter(&params.retrigger_on_play);
+                        }
+                        Action::SetFreezeWhenStopped { value } => {
+                            setter.begin_set_parameter(&params.freeze_when_stopped);
+                            setter.set_parameter(&params.freeze_when_stopped, value);
+                            setter.end_set_parameter(&params.freeze_when_stopped);
+                        }
+                        Action::SetStereoOffset { value } => {
+                            set_normalized_from_ui(&setter, &params.stereo_offset, value);
+                        }
+                        Action::SetBypass { value } => {
+                            setter.begin_set_parameter(&params.bypass);
+                            setter.set_parameter(&params.bypass, value);
+                            setter.end_set_parameter(&params.bypass);
+                        }
+                        Action::SetAnchor { value } => {
+                            if (value as usize) < Anchor::variants().len() {
+                                let anchor = Anchor::from_index(value as usize);
+                                setter.begin_set_parameter(&params.anchor);
+                                setter.set_parameter(&params.anchor, anchor);
+                                setter.end_set_parameter(&params.anchor);
+                            }
+                        }
+                        Action::SetSyncMode { value } => {
+                            if (value as usize) < SyncMode::variants().len() {
+                                let sync_mode = SyncMode::from_index(value as usize);
+                                setter.begin_set_parameter(&params.sync_mode);
+                                setter.set_parameter(&params.sync_mode, sync_mode);
+                                setter.end_set_parameter(&params.sync_mode);
+                            }
+                        }
+                        // The UI sends the free running rate in Hz rather than as a normalized
+                        // value. `rate_hz` uses a skewed 0.1-20 Hz range, so a linear slider
+                        // would otherwise spend most of its travel on the slow rates.
+                        Action::SetRateHz { hz } => {
+                            set_plain_from_ui(&setter, &params.rate_hz, hz);
+                        }
+                        Action::SetPeriodMs { value } => {
+                            set_normalized_from_ui(&setter, &params.period_ms, value);
+                        }
+                        Action::SetAttack { value } => {
+                            set_normalized_from_ui(&setter, &params.attack_ms, value);
+                        }
+                        Action::SetRelease { value } => {
+                            set_normalized_from_ui(&setter, &params.release_ms, value);
+                        }
+                        Action::SetInvert { value } => {
+                            setter.begin_set_parameter(&params.invert);
+                            setter.set_parameter(&params.invert, value);
+                            setter.end_set_parameter(&params.invert);
+                        }
+                        Action::SetPolarity { value } => {
+                            setter.begin_set_parameter(&params.polarity);
+                            setter.set_parameter(&params.polarity, value);
+                            setter.end_set_parameter(&params.polarity);
+                        }
+                        Action::SetDepth { value } => {
+                            set_normalized_from_ui(&setter, &params.depth_db, value);
+                        }
+                        Action::SetHold { value } => {
+                            set_normalized_from_ui(&setter, &params.hold, value);
+                        }
+                        Action::SetOversampling { value } => {
+                            if (value as usize) < Oversampling::variants().len() {
+                                let oversampling = Oversampling::from_index(value as usize);
+                                setter.begin_set_parameter(&params.oversampling);
+                                setter.set_parameter(&params.oversampling, oversampling);
+                                setter.end_set_parameter(&params.oversampling);
+                            }
+                        }
+                        Action::SetQuality { value } => {
+                            if (value as usize) < Quality::variants().len() {
+                                let quality = Quality::from_index(value as usize);
+                                setter.begin_set_parameter(&params.quality);
+                                setter.set_parameter(&params.quality, quality);
+                                setter.end_set_parameter(&params.quality);
+                            }
+                        }
+                        Action::SetLookahead { value } => {
+                            set_normalized_from_ui(&setter, &params.lookahead_ms, value);
+                        }
+                        Action::SetSafetyLimit { value } => {
+                            setter.begin_set_parameter(&params.safety_limit);
+                            setter.set_parameter(&params.safety_limit, value);
+                            setter.end_set_parameter(&params.safety_limit);
+                        }
+                        Action::SetCcOutput { value } => {
+                            setter.begin_set_parameter(&params.cc_output);
+                            setter.set_parameter(&params.cc_output, value);
+                            setter.end_set_parameter(&params.cc_output);
+                        }
+                        Action::SetCcNumber { value } => {
+                            let normalized = params.cc_number.preview_normalized(value as i32);
+                            set_normalized_from_ui(&setter, &params.cc_number, normalized);
+                        }
+                        Action::SetGainMatch { value } => {
+                            setter.begin_set_parameter(&params.gain_match);
+                            setter.set_parameter(&params.gain_match, value);
+                            setter.end_set_parameter(&params.gain_match);
+                        }
+                        Action::SetBandSplit { value } => {
+                            setter.begin_set_parameter(&params.band_split);
+                            setter.set_parameter(&params.band_split, value);
+                            setter.end_set_parameter(&params.band_split);
+                        }
+                        Action::SetCrossover { value } => {
+                            set_normalized_from_ui(&setter, &params.crossover_hz, value);
+                        }
+                        Action::SetBandAmounts { low, high } => {
+                            set_normalized_from_ui(&setter, &params.low_amount, low);
+                            set_normalized_from_ui(&setter, &params.high_amount, high);
+                        }
+                        Action::SetDcFilter { value } => {
+                            setter.begin_set_parameter(&params.dc_filter);
+                            setter.set_parameter(&params.dc_filter, value);
+                            setter.end_set_parameter(&params.dc_filter);
+                        }
+                        Action::SetCeiling { value } => {
+                            set_normalized_from_ui(&setter, &params.ceiling_db, value);
+                        }
+                        Action::SetFloor { value } => {
+                            set_normalized_from_ui(&setter, &params.floor_db, value);
+                        }
+                        Action::SetAttackPow { value } => {
+                            set_normalized_from_ui(&setter, &params.attack_pow, value);
+                        }
+                        Action::SetRmsWindow { value } => {
+                            set_normalized_from_ui(&setter, &params.rms_window_ms, value);
+                        }
+                        Action::SetIntensity { value } => {
+                            set_normalized_from_ui(&setter, &params.intensity, value);
+                            // Some hosts only apply the change later, so reading the parameter
+                            // back here could still return the previous intensity
+                            if !value.is_nan() {
+                                let intensity =
+                                    params.intensity.preview_plain(value.clamp(0.0, 1.0));
+                                let (amount, release_pow, depth_db) = intensity_targets(intensity);
+                                set_plain_from_ui(&setter, &params.amount, amount);
+                                set_plain_from_ui(&setter, &params.release_pow, release_pow);
+                                set_plain_from_ui(&setter, &params.depth_db, depth_db);
+                            }
+                        }
+                        Action::SetListen { value } => {
+                            if (value as usize) < Listen::variants().len() {
+                                let listen = Listen::from_index(value as usize);
+                                setter.begin_set_parameter(&params.listen);
+                                setter.set_parameter(&params.listen, listen);
+                                setter.end_set_parameter(&params.listen);
+                            }
+                        }
+                        Action::SetTestTone { value } => {
+                            setter.begin_set_parameter(&params.test_tone);
+                            setter.set_parameter(&params.test_tone, value);
+                            setter.end_set_parameter(&params.test_tone);
+                        }
+                        Action::SetToneHz { value } => {
+                            set_normalized_from_ui(&setter, &params.tone_hz, value);
+                        }
+                        Action::SetStereoLink { value } => {
+                            setter.begin_set_parameter(&params.stereo_link);
+                            setter.set_parameter(&params.stereo_link, value);
+                            setter.end_set_parameter(&params.stereo_link);
+                        }
+                        Action::SetEmitKey { value } => {
+                            setter.begin_set_parameter(&params.emit_key);
+                            setter.set_parameter(&params.emit_key, value);
+                            setter.end_set_parameter(&params.emit_key);
+                        }
+                        Action::SetTempoMult { value } => {
+                            if (value as usize) < TempoMult::variants().len() {
+                                let tempo_mult = TempoMult::from_index(value as usize);
+                                setter.begin_set_parameter(&params.tempo_mult);
+                                setter.set_parameter(&params.tempo_mult, tempo_mult);
+                                setter.end_set_parameter(&params.tempo_mult);
+                            }
+                        }
+                        Action::SetBias { value } => {
+                            set_normalized_from_ui(&setter, &params.bias, value);
+                        }
+                        Action::SetCustom { value } => {
+                            setter.begin_set_parameter(&params.custom);
+                            setter.set_parameter(&params.custom, value);
+                            setter.end_set_parameter(&params.custom);
+                        }
+                        Action::SetCustomCurve { points } => {
+                            params.custom_curve_table.set(&points);
+                            *params.custom_curve.write().unwrap() =
+                                params.custom_curve_table.points();
+
+                            let points = curve_points.load(Ordering::Relaxed);
+                            if points > 0 {
+                                let _ = ctx.send_json(json!({
+                                    "type": "curve",
+                                    "points": params.envelope_curve(points, meters.time_signature())
+                                }));
+                            }
+                        }
+                        // Randomizing sets the parameters' change flags, so the web UI is updated
+                        // with the new values below
+                        Action::Randomize { seed } => {
+                            let mut rng = seed.map_or_else(Rng::from_time, Rng::new);
+                            randomize_patch(params.as_ref(), &setter, &mut rng);
+                        }
+                        Action::ResetDefaults => reset_to_defaults(params.as_ref(), &setter),
+                        Action::ToggleBypass => {
+                            setter.begin_set_parameter(&params.bypass);
+                            setter.set_parameter(&params.bypass, !params.bypass.value());
+                            setter.end_set_parameter(&params.bypass);
+                        }
+                        Action::ClearClip => {
+                            meters.clipped.store(false, Ordering::Relaxed);
+                            clip_sent.store(false, Ordering::Relaxed);
+                        }
+                        Action::SetMany { values } => {
+                            let unknown_ids = set_many(params.as_ref(), &setter, &values);
+                            if !unknown_ids.is_empty() {
+                                let _ = ctx.send_json(json!({
+                                    "type": "error",
+                                    "message": format!(
+                                        "unknown parameters: {}",
+                                        unknown_ids.join(", ")
+                                    )
+                                }));
+                            }
+                        }
+                        Action::RequestParamMeta { id } => {
+                            let message = params.param_meta_message(&id).unwrap_or_else(|| {
+                                json!({
+                                    "type": "error",
+                                    "message": format!("unknown parameter: {}", id)
+                                })
+                            });
+                            let _ = ctx.send_json(message);
+                        }
+                        Action::SetPeakHold { value } => {
+                            set_normalized_from_ui(&setter, &params.peak_hold_ms, value);
+                        }
+                        Action::SetMode { value } => {
+                            if (value as usize) < Mode::variants().len() {
+                                let mode = Mode::from_index(value as usize);
+                                setter.begin_set_parameter(&params.mode);
+                                setter.set_parameter(&params.mode, mode);
+                                setter.end_set_parameter(&params.mode);
+                            }
+                        }
+                        Action::SetThreshold { value } => {
+                            set_normalized_from_ui(&setter, &params.threshold_db, value);
+                        }
+                        Action::SetTremoloDepth { value } => {
+                            set_normalized_from_ui(&setter, &params.tremolo_depth_db, value);
+                        }
+                        Action::OpenUrl { url } => {
+                            let result = if ALLOWED_URLS.contains(&url.as_str()) {
+                                open_url(&url).map_err(|err| err.to_string())
+                            } else {
+                                Err(String::from("this link is not allowed"))
+                            };
+                            let _ = ctx.send_json(match result {
+                                Ok(()) => json!({ "type": "open_url", "url": url, "ok": true }),
+                                Err(message) => json!({
+                                    "type": "open_url",
+                                    "url": url,
+                                    "ok": false,
+                                    "message": message
+                                }),
+                            });
+                        }
+                        Action::SetBackgroundColor { r, g, b, a } => {
+                            *params.bg_color.write().unwrap() = BackgroundColor { r, g, b, a };
+                        }
+                        // `clamp()` passes NaN through, so that's rejected separately
+                        Action::SetUiScale { scale } => {
+                            if !scale.is_nan() {
+                                let scale = scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE);
+                                *params.ui_scale.write().unwrap() = scale;
+                                let window_size = params.editor_size.read().unwrap().scaled(scale);
+                                ctx.resize(window, window_size.width, window_size.height);
+                                let _ = ctx.send_json(json!({
+                                    "type": "ui_scale",
+                                    "scale": scale
+                                }));
+                            }
+                        }
+                        Action::SetTremoloShape { value } => {
+                            if (value as usize) < EnvShape::variants().len() {
+                                let shape = EnvShape::from_index(value as usize);
+                                setter.begin_set_parameter(&params.tremolo_shape);
+                                setter.set_parameter(&params.tremolo_shape, shape);
+                                setter.end_set_parameter(&params.tremolo_shape);
+                            }
+                        }
+                        Action::SetSize { width, height } => {
+                            let size = size_constraints
+                                .lock()
+                                .unwrap()
+                                .apply(EditorSize { width, height });
+                            let window_size = size.scaled(*params.ui_scale.read().unwrap());
+                            ctx.resize(window, window_size.width, window_size.height);
+                            *params.editor_size.write().unwrap() = size;
+
+                            // The web UI tracks the size while dragging, so it needs to know when
+                            // the size it asked for got clamped or adjusted to the aspect ratio
+                            if size.width != width || size.height != height {
+                                let _ = ctx.send_json(json!({
+                                    "type": "set_size",
+                                    "width": size.width,
+                                    "height": size.height
+                                }));
+                            }
+                        }
+                        Action::SetSizeConstraints(limits) => {
+                            size_constraints.lock().unwrap().set_limits(limits);
+                            constraints_changed = true;
+                        }
+                        Action::SetAspectLock { ratio } => {
+                            size_constraints.lock().unwrap().set_aspect_ratio(ratio);
+                            constraints_changed = true;
+                        }
+                        // These take the parameters' plain values so the web UI can accept
+                        // values in their own units without knowing the parameters' ranges
+                        Action::SetGainDb { db } => {
+                            set_plain_from_ui(&setter, &params.gain, util::db_to_gain(db));
+                        }
+                        Action::SetReleasePowValue { value } => {
+                            set_plain_from_ui(&setter, &params.release_pow, value);
+                        }
+                        Action::SetAmountValue { value } => {
+                            set_plain_from_ui(&setter, &params.amount, value);
+                        }
+                        Action::RequestCurve { points } => {
+                            let points = points
+                                .unwrap_or(DEFAULT_CURVE_POINTS)
+                                .clamp(1, MAX_CURVE_POINTS);
+                            curve_points.store(points, Ordering::Relaxed);
+                            let _ = ctx.send_json(json!({
+                                "type": "curve",
+                                "points": params.envelope_curve(points, meters.time_signature())
+                            }));
+                        }
+                        // This runs on the GUI thread, so the audio thread is never blocked by
+                        // the file IO
+                        Action::SavePreset { path } => {
+                            let result =
+                                Preset::from_params(params.as_ref()).write(Path::new(&path));
+                            if let Err(err) = &result {
+                                nih_log!("Could not save the preset to {:?}: {}", path, err);
+                            }
+                            let _ = ctx.send_json(json!({
+                                "type": "save_result",
+                                "ok": result.is_ok(),
+                                "error": result.err().map(|err| err.to_string())
+                            }));
+                        }
+                        Action::RequestState => {
+                            let _ = ctx.send_json(params.full_state_message());
+                        }
+                        Action::LoadFactoryPreset { index } => {
+                            if let Some(preset) = FACTORY_PRESETS.get(index) {
+                                preset.apply(params.as_ref(), &setter);
+                            }
+                        }
+                        // Recalling a snapshot sets the parameters' change flags, so the web UI
+                        // is updated with the recalled values below
+                        Action::StoreSnapshot { slot } => match snapshot_index(slot) {
+                            Some(index) => {
+                                let mut snapshots = snapshots.lock().unwrap();
+                                snapshots[index] = Some(Preset::from_params(params.as_ref()));
+                                let _ = ctx.send_json(snapshots_message(&snapshots));
+                            }
+                            None => nih_log!("Unknown snapshot slot: {}", slot),
+                        },
+                        Action::RecallSnapshot { slot } => match snapshot_index(slot) {
+                            Some(index) => {
+                                if let Some(snapshot) = &snapshots.lock().unwrap()[index] {
+                                    snapshot.apply(params.as_ref(), &setter);
+                                }
+                            }
+                            None => nih_log!("Unknown snapshot slot: {}", slot),
+                        },
+                        Action::CopyAtoB => {
+                            let mut snapshots = snapshots.lock().unwrap();
+                            snapshots[1] = snapshots[0].clone();
+                            let _ = ctx.send_json(snapshots_message(&snapshots));
+                        }
+                        // Every tap marks a beat, so the free running cycle is set to last as
+                        // many taps as it has beats. Both free running modes are set so the
+                        // tempo sticks when switching between them.
+                        Action::Tap => {
+                            if let Some(bpm) = tap_tempo.lock().unwrap().tap(Instant::now()) {
+                                let cycle_beats = params.length.value() as f64
+                                    * params.division.value().beats(meters.time_signature())
+                                    * params.modifier.value().scale();
+                                // The cycle doesn't run with a length of 0, but a later length
+                                // change still starts from a tempo close to the tapped one
+                                let cycle_beats = if cycle_beats > 0.0 { cycle_beats } else { 1.0 };
+                                let period_seconds = cycle_beats * 60.0 / bpm;
+                                set_plain_from_ui(
+                                    &setter,
+                                    &params.rate_hz,
+                                    period_seconds.recip() as f32,
+                                );
+                                set_plain_from_ui(
+                                    &setter,
+                                    &params.period_ms,
+                                    (period_seconds * 1000.0) as f32,
+                                );
+                                let _ = ctx.send_json(json!({
+                                    "type": "tap_tempo",
+                                    "bpm": bpm
+                                }));
+                            }
+                        }
+                        Action::Init => {
+                            let size = size_constraints
+                                .lock()
+                                .unwrap()
+                                .apply(*params.editor_size.read().unwrap());
+                            let ui_scale = *params.ui_scale.read().unwrap();
+                            let window_size = size.scaled(ui_scale);
+                            ctx.resize(window, window_size.width, window_size.height);
+                            // The web UI zooms its contents before it gets the unscaled size
+                            let _ = ctx.send_json(json!({
+                                "type": "ui_scale",
+                                "scale": ui_scale
+                            }));
+                            let sample_rate = meters.sample_rate.load();
+                            let latency_samples = meters.latency_samples.load(Ordering::Relaxed);
+                            last_sent_sample_rate.store(sample_rate);
+                            last_sent_latency_samples.store(latency_samples, Ordering::Relaxed);
+                            let _ = ctx.send_json(json!({
+                                "type": "set_size",
+                                "width": size.width,
+                                "height": size.height,
+                                "sample_rate": sample_rate,
+                                "latency_samples": latency_samples
+                            }));
+                            let _ = ctx.send_json(json!({
+                                "type": "presets",
+                                "names": FACTORY_PRESETS
+                                    .iter()
+                                    .map(|preset| preset.name)
+                                    .collect::<Vec<_>>()
+                            }));
+                            let _ = ctx.send_json(snapshots_message(&snapshots.lock().unwrap()));
+                            let _ = ctx.send_json(json!({
+                                "type": "custom_curve",
+                                "points": params.custom_curve_table.points()
+                            }));
+                            let _ = ctx.send_json(json!({
+                                "type": "background_color",
+                                "color": *params.bg_color.read().unwrap()
+                            }));
+                        }
+                    },
+                    // A malformed message from the web UI should never take down the host
+                    Err(err) => {
+                        nih_log!("Invalid action received from web UI: {}", err);
+                        let _ = ctx.send_json(json!({
+                            "type": "error",
+                            "message": format!("unknown action: {}", err)
+                        }));
+                    }
+                }
+            }
+
+            // The current size is fitted to new constraints once all messages have been handled
+            if constraints_changed {
+                let current = *params.editor_size.read().unwrap();
+                let size = size_constraints.lock().unwrap().apply(current);
+                if size.width != current.width || size.height != current.height {
+                    let window_size = size.scaled(*params.ui_scale.read().unwrap());
+                    ctx.resize(window, window_size.width, window_size.height);
+                    *params.editor_size.write().unwrap() = size;
+                    let _ = ctx.send_json(json!({
+                        "type": "set_size",
+                        "width": size.width,
+                        "height": size.height
+                    }));
+                }
+            }
+
+            // Applying the preset sets the parameters' change flags, so the web UI is updated
+            // with the new values below
+            let dropped_preset = dropped_preset.lock().unwrap().take();
+            if let Some(path) = dropped_preset {
+                match Preset::read(&path) {
+                    Ok(preset) => preset.apply(params.as_ref(), &setter),
+                    Err(err) => nih_log!("Could not load the preset from {:?}: {}", path, err),
+                }
+            }
+
+            let mut curve_changed = false;
+            for (id, value_changed) in params.change_flags() {
+                if value_changed.swap(false, Ordering::Relaxed) {
+                    if let Some(message) = params.param_change_message(id) {
+                        let _ = ctx.send_json(message);
+                    }
+                    // The length's label depends on the division and modifier
+                    if matches!(id, "division" | "modifier") {
+                        if let Some(message) = params.param_change_message("length") {
+                            let _ = ctx.send_json(message);
+                        }
+                    }
+
+                    curve_changed |= matches!(
+                        id,
+                        "length"
+                            | "attack_pow"
+                            | "release_pow"
+                            | "amount"
+                            | "depth_db"
+                            | "hold"
+                            | "bias"
+                            | "custom"
+                            | "floor_db"
+                            | "shape"
+                            | "division"
+                            | "modifier"
+                            | "invert"
+                            | "second_env"
+                            | "length2"
+                            | "release_pow2"
+                            | "amount2"
+                    );
+                }
+            }
+
+            let points = curve_points.load(Ordering::Relaxed);
+            if curve_changed && points > 0 {
+                let _ = ctx.send_json(json!({
+                    "type": "curve",
+                    "points": params.envelope_curve(points, meters.time_signature())
+                }));
+            }
+
+            let reduction_db = meters.gain_reduction_db.load();
+            if (reduction_db - last_sent_reduction_db.load()).abs() > METER_THRESHOLD_DB {
+                last_sent_reduction_db.store(reduction_db);
+                let _ = ctx.send_json(json!({
+                    "type": "meter",
+                    "reduction_db": reduction_db
+                }));
+            }
+
+            let mut last_levels_sent = last_levels_sent.lock().unwrap();
+            if last_levels_sent.elapsed() >= LEVELS_INTERVAL {
+                *last_levels_sent = Instant::now();
+                let _ = ctx.send_json(json!({
+                    "type": "levels",
+                    "in": util::gain_to_db(meters.input_rms.load()),
+                    "out": util::gain_to_db(meters.output_rms.load()),
+                    "pre_trim_peak": util::gain_to_db(meters.pre_trim_peak.load()),
+                    "in_peak": util::gain_to_db(meters.input_peak.load()),
+                    "out_peak": util::gain_to_db(meters.output_peak.load()),
+                    "in_hold": util::gain_to_db(meters.input_hold.load()),
+                    "out_hold": util::gain_to_db(meters.output_hold.load()),
+                    // The per channel peaks, with a single element for mono layouts
+                    "in_channels": channel_peaks_db(&meters.input_channel_peaks),
+                    "out_channels": channel_peaks_db(&meters.output_channel_peaks)
+                }));
+                let correlation = meters.correlation.load();
+                if !correlation.is_nan() {
+                    let _ = ctx.send_json(json!({
+                        "type": "correlation",
+                        "value": correlation
+                    }));
+                }
+
+                // The playhead stays where it is while the transport is stopped, even if a free
+                // running cycle keeps moving
+                if meters.playing.load(Ordering::Relaxed) {
+                    let _ = ctx.send_json(json!({
+                        "type": "playhead",
+                        "phase": meters.beat_phase.load()
+                    }));
+                }
+            }
+
+            let mut frames_read = scope_frames_read.load(Ordering::Relaxed);
+            let mut scope_points = Vec::new();
+            meters.scope.drain(&mut frames_read, &mut scope_points);
+            scope_frames_read.store(frames_read, Ordering::Relaxed);
+            if !scope_points.is_empty() {
+                let _ = ctx.send_json(json!({
+                    "type": "scope",
+                    "samples": scope_points
+                }));
+            }
+
+            if meters.clipped.load(Ordering::Relaxed) && !clip_sent.swap(true, Ordering::Relaxed) {
+                let _ = ctx.send_json(json!({ "type": "clip" }));
+            }
+
+            let tempo_available = meters.tempo_available.load(Ordering::Relaxed);
+            if last_sent_tempo_available.swap(tempo_available, Ordering::Relaxed) != tempo_available
+            {
+                let _ = ctx.send_json(json!({
+                    "type": "tempo_status",
+                    "available": tempo_available
+                }));
+            }
+
+            // The sample rate changes when the host reinitializes the plugin, and the latency
+            // changes with the lookahead and oversampling settings
+            let sample_rate = meters.sample_rate.load();
+            let latency_samples = meters.latency_samples.load(Ordering::Relaxed);
+            if sample_rate != last_sent_sample_rate.load()
+                || latency_samples != last_sent_latency_samples.load(Ordering::Relaxed)
+            {
+                last_sent_sample_rate.store(sample_rate);
+                last_sent_latency_samples.store(latency_samples, Ordering::Relaxed);
+                let _ = ctx.send_json(json!({
+                    "type": "audio_info",
+                    "sample_rate": sample_rate,
+                    "latency_samples": latency_samples
+                }));
+            }
+
+            // The tempo falls back to the last tempo the host reported, or 120 BPM if it never
+            // reported one
+            let mut last_transport_sent = last_transport_sent.lock().unwrap();
+            if last_transport_sent.elapsed() >= TRANSPORT_INTERVAL {
+                *last_transport_sent = Instant::now();
+                let _ = ctx.send_json(json!({
+                    "type": "transport",
+                    "tempo": meters.tempo.load(),
+                    "beat_phase": meters.beat_phase.load()
+                }));
+            }
+        });
+
+        Some(Box::new(editor))
+    }
+
+    fn deactivate(&mut self) {}
+}
+
+impl ClapPlugin for SoutGainRs {
+    const CLAP_ID: &'static str = "org.eu.sout.audio.exgainwv";
+    const CLAP_DESCRIPTION: Option<&'static str> = Some("it is a gain plugin written by rust");
+    const CLAP_MANUAL_URL: Option<&'static str> = Some(Self::URL);
+    const CLAP_SUPPORT_URL: Option<&'static str> = None;
+    const CLAP_FEATURES: &'static [ClapFeature] = &[
+        ClapFeature::AudioEffect,
+        ClapFeature::Stereo,
+        ClapFeature::Mono,
+        ClapFeature::Utility,
+    ];
+}
+
+impl Vst3Plugin for SoutGainRs {
+    const VST3_CLASS_ID: [u8; 16] = *b"SoutGainWebView!";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
+        &[Vst3SubCategory::Fx, Vst3SubCategory::Tools];
+}
+
+// Forks need to change both IDs, or hosts will mistake them for this plugin. The VST3 class ID's
+// length is already enforced by its type.
+const _: () = assert!(
+    is_reverse_dns(<SoutGainRs as ClapPlugin>::CLAP_ID),
+    "CLAP_ID needs to be a reverse domain name like `com.example.plugin`"
+);
+
 nih_export_clap!(SoutGainRs);
 nih_export_vst3!(SoutGainRs);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unity_width_leaves_stereo_unchanged() {
+        let (left, right) = stereo_width(0.8, -0.3, 1.0);
+        assert!((left - 0.8).abs() < 1e-6, "{left}");
+        assert!((right - -0.3).abs() < 1e-6, "{right}");
+    }
+
+    #[test]
+    fn zero_width_collapses_to_mono() {
+        let (left, right) = stereo_width(0.8, -0.3, 0.0);
+        assert!((left - 0.25).abs() < 1e-6, "{left}");
+        assert_eq!(left, right);
+    }
+
+    #[test]
+    fn lookahead_ducks_ahead_of_the_transient() {
+        let sample_rate = 48000.0;
+        let attack_coef = one_pole_coefficient(SIDECHAIN_ATTACK_MS, sample_rate);
+        let release_coef = one_pole_coefficient(SIDECHAIN_RELEASE_MS, sample_rate);
+        // 5 ms at 48 kHz
+        let max_lookahead_samples = 240;
+        let transient_idx = 1000;
+
+        // The detector's level when a step in the input reaches the output
+        let level_at_transient = |lookahead_samples: usize| {
+            let mut delay = DelayLine::new(max_lookahead_samples);
+            let mut detector = 0.0;
+            (0..)
+                .find_map(|sample_idx| {
+                    let input = if sample_idx >= transient_idx {
+                        1.0
+                    } else {
+                        0.0
+                    };
+                    let (output, level) = lookahead(
+                        input,
+                        Some(&mut detector),
+                        Some(&mut delay),
+                        lookahead_samples,
+                        attack_coef,
+                        release_coef,
+                    );
+                    (output > 0.0).then_some(level)
+                })
+                .unwrap()
+        };
+
+        let without_lookahead = level_at_transient(0);
+        let with_lookahead = level_at_transient(max_lookahead_samples);
+        assert!(without_lookahead < 0.1, "{without_lookahead}");
+        assert!(with_lookahead > 0.9, "{with_lookahead}");
+
+        // Without a detector the level follows the input before it's delayed
+        let mut delay = DelayLine::new(max_lookahead_samples);
+        let (output, level) = lookahead(0.5, None, Some(&mut delay), 10, attack_coef, release_coef);
+        assert_eq!((output, level), (0.0, 0.5));
+    }
+
+    #[test]
+    fn cascaded_envelopes_multiply_their_gains() {
+        let envelope = DuckEnvelope {
+            shape: EnvShape::Linear,
+            custom_curve: None,
+            attack_pow: 1.0,
+            release_pow: 10.0,
+            amount: 0.5,
+            depth_db: 50.0,
+            hold: 0.0,
+            bias: 0.5,
+        };
+        let second_envelope = DuckEnvelope {
+            amount: 0.8,
+            ..envelope
+        };
+
+        // A 4 beat cycle layered with a 3 beat cycle. Both start at their full depth, and the
+        // second one starts over three quarters of the way through the first one.
+        for (phase, expected_db) in [
+            (0.0, -25.0 - 40.0),
+            (0.375, -15.625 - 20.0),
+            (0.75, -6.25 - 40.0),
+        ] {
+            let second_phase = second_cycle_phase(phase, 4.0, 3.0);
+            let combined_db = cascade_duck_db(
+                compute_duck_db(&envelope, phase, 4.0),
+                compute_duck_db(&second_envelope, second_phase, 3.0),
+            );
+            assert!(
+                (combined_db - expected_db).abs() < 1e-3,
+                "{combined_db} != {expected_db} at {phase}"
+            );
+
+            let gain = util::db_to_gain(combined_db);
+            let expected = util::db_to_gain(-25.0 * (1.0 - phase))
+                * util::db_to_gain(-40.0 * (1.0 - second_phase));
+            assert!((gain - expected).abs() < 1e-5, "{gain} != {expected}");
+        }
+    }
+
+    #[test]
+    fn soft_clip_never_exceeds_the_ceiling() {
+        for ceiling_db in [-12.0, -1.0, 0.0, 6.0] {
+            let ceiling = util::db_to_gain(ceiling_db);
+            for i in 0..=4000 {
+                let sample = -20.0 + i as f32 * 0.01;
+                let clipped = soft_clip(sample, ceiling);
+                assert!(
+                    clipped.abs() <= ceiling,
+                    "{sample} at {ceiling_db} dB: {clipped}"
+                );
+                assert_eq!(clipped.signum(), sample.signum(), "{sample}");
+            }
+
+            // Quiet samples pass through unchanged
+            let quiet = ceiling * SOFT_CLIP_KNEE * 0.5;
+            assert_eq!(soft_clip(quiet, ceiling), quiet);
+            assert_eq!(soft_clip(-quiet, ceiling), -quiet);
+        }
+    }
+
+    #[test]
+    fn linked_sidechain_follows_the_loudest_channel() {
+        let mut left = [0.8, -0.1];
+        let mut right = [-0.2, 0.6];
+        let channels: [&mut [f32]; 2] = [&mut left, &mut right];
+
+        for channel_idx in 0..2 {
+            assert_eq!(sidechain_level(Some(&channels), channel_idx, 0, true), 0.8);
+            assert_eq!(sidechain_level(Some(&channels), channel_idx, 1, true), 0.6);
+        }
+    }
+
+    #[test]
+    fn unlinked_sidechain_follows_each_channel() {
+        let mut left = [0.8, -0.1];
+        let mut right = [-0.2, 0.6];
+        let channels: [&mut [f32]; 2] = [&mut left, &mut right];
+
+        assert_eq!(sidechain_level(Some(&channels), 0, 0, false), 0.8);
+        assert_eq!(sidechain_level(Some(&channels), 1, 0, false), 0.2);
+        assert_eq!(sidechain_level(Some(&channels), 0, 1, false), 0.1);
+        assert_eq!(sidechain_level(Some(&channels), 1, 1, false), 0.6);
+        // Layouts with more channels than the sidechain reuse its channels in order
+        assert_eq!(sidechain_level(Some(&channels), 3, 1, false), 0.6);
+        // Without a sidechain input the follower sees silence
+        assert_eq!(sidechain_level(None, 0, 0, false), 0.0);
+    }
+
+    /// A plugin with `params` that has been initialized for `num_channels` channels at 48 kHz and
+    /// then reset, like the host does before it starts processing.
+    fn initialized_plugin(params: GainParams, num_channels: usize) -> SoutGainRs {
+        let mut plugin = SoutGainRs {
+            params: Arc::new(params),
+            sample_rate: 48000.0,
+            ..SoutGainRs::default()
+        };
+        plugin.allocate(num_channels);
+        plugin.reset();
+
+        plugin
+    }
+
+    /// Runs `channels` through the plugin as a single block at the start of the song, with the
+    /// transport stopped and without a sidechain, a key output, or note events.
+    fn process_channels(plugin: &mut SoutGainRs, channels: &mut [Vec<f32>]) {
+        let mut channels: Vec<&mut [f32]> = channels.iter_mut().map(Vec::as_mut_slice).collect();
+        let position = BlockPosition {
+            seconds: 0.0,
+            beats: None,
+            playing: false,
+        };
+        plugin.process_block(&mut channels, None, None, position, || None);
+    }
+
+    /// Ducks every quarter note.
+    fn length_param() -> IntParam {
+        IntParam::new("Lenght", 1, IntRange::Linear { min: 0, max: 4 })
+    }
+
+    #[test]
+    fn polarity_negates_the_output() {
+        // The DC blocker, the soft start, the safety limit, and the mix are all symmetric, so the
+        // output stays exactly negated
+        let output = |polarity| {
+            let params = GainParams {
+                length: length_param(),
+                polarity: BoolParam::new("Invert Polarity", polarity),
+                dc_filter: BoolParam::new("DC Filter", true),
+                safety_limit: BoolParam::new("Safety Limit", true),
+                mix: FloatParam::new("Mix", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 }),
+                ..GainParams::default()
+            };
+            let mut plugin = initialized_plugin(params, 2);
+            // Loud enough to reach the limiter
+            let mut channels = vec![
+                (0..2048).map(|i| 1.5 * (i as f32 * 0.1).sin()).collect(),
+                (0..2048).map(|i| 1.5 * (i as f32 * 0.17).cos()).collect(),
+            ];
+            process_channels(&mut plugin, &mut channels);
+
+            channels
+        };
+
+        let normal = output(false);
+        let inverted = output(true);
+        assert!(normal.iter().flatten().any(|sample| sample.abs() > 0.1));
+        for (channel_idx, (normal, inverted)) in normal.iter().zip(&inverted).enumerate() {
+            for (sample_idx, (normal, inverted)) in normal.iter().zip(inverted).enumerate() {
+                assert_eq!(
+                    *inverted, -normal,
+                    "channel {channel_idx}, sample {sample_idx}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_channel_of_a_quad_buffer_is_ducked() {
+        let params = GainParams {
+            length: length_param(),
+            stereo_offset: FloatParam::new(
+                "Stereo Offset",
+                0.25,
+                FloatRange::Linear { min: 0.0, max: 0.5 },
+            ),
+            ..GainParams::default()
+        };
+        let mut plugin = initialized_plugin(params, 4);
+        let mut channels = vec![vec![1.0; 2048]; 4];
+        process_channels(&mut plugin, &mut channels);
+
+        for (channel_idx, channel) in channels.iter().enumerate() {
+            assert!(
+                channel.iter().all(|&sample| sample < 1.0),
+                "{channel_idx}: {channel:?}"
+            );
+        }
+        // Both pairs follow the same cycle, with the right channels running ahead. The left
+        // channels are at the bottom of the duck.
+        assert_eq!(channels[0], channels[2]);
+        assert_eq!(channels[1], channels[3]);
+        assert!(channels[0][2047] < 0.5, "{}", channels[0][2047]);
+        assert!(channels[1][2047] > channels[0][2047], "{channels:?}");
+    }
+
+    #[test]
+    fn mono_layout_disables_the_stereo_features() {
+        assert!(!is_stereo(1));
+        // A buffer that doesn't match the negotiated layout isn't treated as pairs either
+        assert!(!is_stereo(3));
+
+        // The only channel is never offset, and follows the loudest sidechain channel
+        assert_eq!(channel_phase(0.3, 0, 0.25), 0.3);
+        let mut left = [0.1];
+        let mut right = [-0.7];
+        let sidechain: [&mut [f32]; 2] = [&mut left, &mut right];
+        assert_eq!(sidechain_level(Some(&sidechain), 0, 0, true), 0.7);
+    }
+
+    #[test]
+    fn stereo_layout_offsets_the_right_channel() {
+        assert!(is_stereo(2));
+        assert!(is_stereo(4));
+
+        assert_eq!(channel_phase(0.3, 0, 0.25), 0.3);
+        assert!((channel_phase(0.3, 1, 0.25) - 0.55).abs() < 1e-6);
+        assert!((channel_phase(0.9, 1, 0.25) - 0.15).abs() < 1e-6);
+
+        // A mono sidechain feeds both channels
+        let mut mono = [-0.4];
+        let sidechain: [&mut [f32]; 1] = [&mut mono];
+        assert_eq!(sidechain_level(Some(&sidechain), 0, 0, false), 0.4);
+        assert_eq!(sidechain_level(Some(&sidechain), 1, 0, false), 0.4);
+    }
+
+    /// The smoothed length after a step from `from` to `to` at 48 kHz, for the 20 ms the smoother
+    /// takes plus a few more samples.
+    fn length_step_response(from: i32, to: i32) -> Vec<i32> {
+        let params = GainParams::default();
+        params.length.smoothed.reset(from);
+        params.length.smoothed.set_target(48000.0, to);
+        (0..1000).map(|_| params.length.smoothed.next()).collect()
+    }
+
+    #[test]
+    fn length_steps_gradually() {
+        let ramp = length_step_response(0, 4);
+        assert!(ramp[0] < 4, "{ramp:?}");
+        assert!(
+            ramp.windows(2)
+                .all(|pair| pair[1] - pair[0] <= 1 && pair[1] >= pair[0]),
+            "{ramp:?}"
+        );
+        // Every length in between is passed through on the way
+        for length in 1..=3 {
+            assert!(ramp.contains(&length), "{length}: {ramp:?}");
+        }
+        assert_eq!(ramp[959..], [4; 41]);
+    }
+
+    #[test]
+    fn length_crosses_from_off_once() {
+        // Turning the effect on only switches from off to a single step once, halfway through
+        let ramp = length_step_response(0, 1);
+        let switch = ramp.iter().position(|&length| length == 1).unwrap();
+        assert!(switch > 400 && switch < 560, "{switch}");
+        assert!(ramp[switch..].iter().all(|&length| length == 1), "{ramp:?}");
+    }
+
+    #[test]
+    fn reverse_dns_ids() {
+        assert!(is_reverse_dns("org.eu.sout.audio.exgainwv"));
+        assert!(is_reverse_dns("com.example.my-plugin_2"));
+        assert!(!is_reverse_dns(""));
+        assert!(!is_reverse_dns("exgainwv"));
+        assert!(!is_reverse_dns("com..example"));
+        assert!(!is_reverse_dns("com.example."));
+        assert!(!is_reverse_dns("com.example plugin"));
+    }
+
+    #[test]
+    fn length_labels_follow_the_division() {
+        assert_eq!(length_label(0, Division::Bar, Modifier::Straight), "Off");
+        assert_eq!(length_label(1, Division::Bar, Modifier::Straight), "1 bar");
+        assert_eq!(length_label(2, Division::Bar, Modifier::Straight), "2 bars");
+        assert_eq!(
+            length_label(3, Division::Eighth, Modifier::Triplet),
+            "3 × 1/8T"
+        );
+    }
+
+    #[test]
+    fn length_steps_round_trip() {
+        let to_string = v2s_length_steps();
+        let to_value = s2v_length_steps();
+        for length in 0..=4 {
+            assert_eq!(to_value(&to_string(length)), Some(length));
+        }
+        assert_eq!(to_value("3"), Some(3));
+    }
+
+    #[test]
+    fn mono_passes_through_at_any_width() {
+        for width in [0.0, 0.5, 1.0, 2.0] {
+            assert_eq!(stereo_width(0.5, 0.5, width), (0.5, 0.5));
+        }
+    }
+}
@@ -0,0 +1,486 @@
+use nih_plug::util;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+use crate::envelope::TimeSignature;
+use crate::scope::Scope;
+
+/// An `f32` that can be shared between the audio thread and the editor, stored as its bit pattern.
+pub struct AtomicF32(AtomicU32);
+
+impl AtomicF32 {
+    pub fn new(value: f32) -> Self {
+        Self(AtomicU32::new(value.to_bits()))
+    }
+
+    pub fn load(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn store(&self, value: f32) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// The most channels the per channel meters track. This covers all of the plugin's layouts, up to
+/// the quad layout.
+pub const MAX_METER_CHANNELS: usize = 4;
+
+/// Decaying peak amplitudes for the individual channels, as linear gain.
+pub struct ChannelPeaks {
+    peaks: [AtomicF32; MAX_METER_CHANNELS],
+    num_channels: AtomicUsize,
+}
+
+impl Default for ChannelPeaks {
+    fn default() -> Self {
+        Self {
+            peaks: std::array::from_fn(|_| AtomicF32::new(0.0)),
+            num_channels: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl ChannelPeaks {
+    /// The stored peaks for all channels, including the ones the current layout doesn't use.
+    /// This doesn't allocate, so it can be used on the audio thread.
+    pub fn load_all(&self) -> [f32; MAX_METER_CHANNELS] {
+        std::array::from_fn(|channel_idx| self.peaks[channel_idx].load())
+    }
+
+    /// The peaks for the channels stored during the last `store()`.
+    pub fn load(&self) -> Vec<f32> {
+        let num_channels = self.num_channels.load(Ordering::Relaxed);
+        self.peaks[..num_channels]
+            .iter()
+            .map(AtomicF32::load)
+            .collect()
+    }
+
+    /// Stores the peaks for `peaks.len()` channels. Channels past `MAX_METER_CHANNELS` are
+    /// ignored.
+    pub fn store(&self, peaks: &[f32]) {
+        let num_channels = peaks.len().min(MAX_METER_CHANNELS);
+        for (atomic, peak) in self.peaks.iter().zip(&peaks[..num_channels]) {
+            atomic.store(*peak);
+        }
+        self.num_channels.store(num_channels, Ordering::Relaxed);
+    }
+
+    /// Clears the stored peaks for every channel so the meters don't decay from stale values.
+    pub fn reset(&self) {
+        for atomic in &self.peaks {
+            atomic.store(0.0);
+        }
+        self.num_channels.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Values computed on the audio thread that the editor sends to the web UI's meters and transport
+/// display.
+pub struct Meters {
+    /// The deepest gain reduction applied during the last processed block, in decibels.
+    pub gain_reduction_db: AtomicF32,
+    /// The decaying peak amplitude of the input before the input trim, as linear gain.
+    pub pre_trim_peak: AtomicF32,
+    /// The decaying peak amplitude of the input before processing, as linear gain.
+    pub input_peak: AtomicF32,
+    /// The decaying peak amplitude of the output after processing, as linear gain.
+    pub output_peak: AtomicF32,
+    /// The decaying peak amplitudes of the input's individual channels.
+    pub input_channel_peaks: ChannelPeaks,
+    /// The decaying peak amplitudes of the output's individual channels.
+    pub output_channel_peaks: ChannelPeaks,
+    /// The input's peak hold marker, as linear gain.
+    pub input_hold: AtomicF32,
+    /// The output's peak hold marker, as linear gain.
+    pub output_hold: AtomicF32,
+    /// The RMS level of the input over the `rms_window_ms` window, as linear gain.
+    pub input_rms: AtomicF32,
+    /// The RMS level of the output over the `rms_window_ms` window, as linear gain.
+    pub output_rms: AtomicF32,
+    /// The output's stereo correlation, see [`Correlation`]. This is NaN for layouts that aren't a
+    /// single stereo pair.
+    pub correlation: AtomicF32,
+    /// The tempo the envelope followed during the last processed block, in beats per minute.
+    pub tempo: AtomicF32,
+    /// Whether the host reported a tempo during the last processed block. If it didn't, `tempo`
+    /// is the last tempo the host did report.
+    pub tempo_available: AtomicBool,
+    /// The normalized position within the envelope's cycle at the end of the last processed
+    /// block.
+    pub beat_phase: AtomicF32,
+    /// Whether the host's transport was playing during the last processed block. The web UI's
+    /// playhead only moves while this is set.
+    pub playing: AtomicBool,
+    /// Set when the output goes above 0 dBFS. This stays set until the web UI clears it.
+    pub clipped: AtomicBool,
+    /// Peak decimated frames of the output signal.
+    pub scope: Scope,
+    /// The sample rate the plugin was last initialized with, in Hz.
+    pub sample_rate: AtomicF32,
+    /// The latency last reported to the host, in samples.
+    pub latency_samples: AtomicU32,
+    /// The last time signature the host reported.
+    pub time_sig_numerator: AtomicU32,
+    pub time_sig_denominator: AtomicU32,
+}
+
+impl Default for Meters {
+    fn default() -> Self {
+        Self {
+            gain_reduction_db: AtomicF32::new(0.0),
+            pre_trim_peak: AtomicF32::new(0.0),
+            input_peak: AtomicF32::new(0.0),
+            output_peak: AtomicF32::new(0.0),
+            input_channel_peaks: ChannelPeaks::default(),
+            output_channel_peaks: ChannelPeaks::default(),
+            input_hold: AtomicF32::new(0.0),
+            output_hold: AtomicF32::new(0.0),
+            input_rms: AtomicF32::new(0.0),
+            output_rms: AtomicF32::new(0.0),
+            correlation: AtomicF32::new(f32::NAN),
+            tempo: AtomicF32::new(120.0),
+            tempo_available: AtomicBool::new(true),
+            beat_phase: AtomicF32::new(0.0),
+            playing: AtomicBool::new(false),
+            clipped: AtomicBool::new(false),
+            scope: Scope::default(),
+            sample_rate: AtomicF32::new(44100.0),
+            latency_samples: AtomicU32::new(0),
+            time_sig_numerator: AtomicU32::new(TimeSignature::COMMON_TIME.numerator),
+            time_sig_denominator: AtomicU32::new(TimeSignature::COMMON_TIME.denominator),
+        }
+    }
+}
+
+impl Meters {
+    /// The last time signature the host reported.
+    pub fn time_signature(&self) -> TimeSignature {
+        TimeSignature {
+            numerator: self.time_sig_numerator.load(Ordering::Relaxed),
+            denominator: self.time_sig_denominator.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Updates a decaying peak meter with a new sample's amplitude. The meter jumps up to louder
+/// peaks immediately and falls back down at a rate set by `decay_weight`.
+pub fn decay_peak(current: f32, amplitude: f32, decay_weight: f32) -> f32 {
+    if amplitude > current {
+        amplitude
+    } else {
+        current * decay_weight + amplitude * (1.0 - decay_weight)
+    }
+}
+
+/// A peak hold marker. The marker jumps up to louder peaks immediately, stays there for a number
+/// of samples, and only then falls back down like a decaying peak meter.
+#[derive(Default)]
+pub struct PeakHold {
+    value: f32,
+    samples_left: usize,
+}
+
+impl PeakHold {
+    /// Updates the marker with a new sample's amplitude and returns the marker's value. Louder
+    /// peaks restart the hold for `hold_samples` samples.
+    pub fn process(&mut self, amplitude: f32, hold_samples: usize, decay_weight: f32) -> f32 {
+        if amplitude >= self.value {
+            self.value = amplitude;
+            self.samples_left = hold_samples;
+        } else if self.samples_left > 0 {
+            self.samples_left -= 1;
+        } else {
+            self.value = decay_peak(self.value, amplitude, decay_weight);
+        }
+
+        self.value
+    }
+
+    /// The marker's current value.
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    pub fn reset(&mut self) {
+        self.value = 0.0;
+        self.samples_left = 0;
+    }
+}
+
+/// A running RMS level over a sliding window with a variable length. The buffer is allocated up
+/// front for the longest window, so changing the window's length never allocates.
+pub struct RmsWindow {
+    squares: Vec<f32>,
+    pos: usize,
+    len: usize,
+    sum: f64,
+}
+
+impl RmsWindow {
+    pub fn new(max_len: usize) -> Self {
+        let max_len = max_len.max(1);
+
+        Self {
+            squares: vec![0.0; max_len],
+            pos: 0,
+            len: max_len,
+            sum: 0.0,
+        }
+    }
+
+    /// Changes the window's length in samples, limited to the length it was allocated for.
+    pub fn set_len(&mut self, len: usize) {
+        let len = len.clamp(1, self.squares.len());
+        if len == self.len {
+            return;
+        }
+
+        // The sum is recomputed from scratch for the new window, which also gets rid of any
+        // accumulated rounding errors
+        let capacity = self.squares.len();
+        self.len = len;
+        self.sum = (1..=len)
+            .map(|age| self.squares[(self.pos + capacity - age) % capacity] as f64)
+            .sum();
+    }
+
+    /// Adds a new mean square value to the window, dropping the oldest value.
+    pub fn push(&mut self, square: f32) {
+        let capacity = self.squares.len();
+        self.sum -= self.squares[(self.pos + capacity - self.len) % capacity] as f64;
+        self.squares[self.pos] = square;
+        self.sum += square as f64;
+        self.pos = (self.pos + 1) % capacity;
+    }
+
+    /// Clears the window, as if only silence had been pushed.
+    pub fn reset(&mut self) {
+        self.squares.fill(0.0);
+        self.pos = 0;
+        self.sum = 0.0;
+    }
+
+    /// The RMS level over the window.
+    pub fn rms(&self) -> f32 {
+        (self.sum.max(0.0) / self.len as f64).sqrt() as f32
+    }
+}
+
+/// The normalized cross-correlation between the left and right channels over a sliding window,
+/// from 1 for identical channels through 0 for unrelated channels to -1 for channels with opposite
+/// polarity. Negative values mean the channels partly cancel out when summed to mono. Silence
+/// reads as 0.
+pub struct Correlation {
+    /// The product of the two channels and the squares of each channel for every sample in the
+    /// window.
+    products: Vec<[f32; 3]>,
+    pos: usize,
+    sums: [f64; 3],
+}
+
+impl Correlation {
+    pub fn new(len: usize) -> Self {
+        Self {
+            products: vec![[0.0; 3]; len.max(1)],
+            pos: 0,
+            sums: [0.0; 3],
+        }
+    }
+
+    /// Adds a new pair of samples to the window, dropping the oldest pair.
+    pub fn push(&mut self, left: f32, right: f32) {
+        let products = [left * right, left * left, right * right];
+        for ((sum, old), new) in self
+            .sums
+            .iter_mut()
+            .zip(self.products[self.pos])
+            .zip(products)
+        {
+            *sum += new as f64 - old as f64;
+        }
+        self.products[self.pos] = products;
+        self.pos = (self.pos + 1) % self.products.len();
+    }
+
+    /// Clears the window, as if only silence had been pushed.
+    pub fn reset(&mut self) {
+        self.products.fill([0.0; 3]);
+        self.pos = 0;
+        self.sums = [0.0; 3];
+    }
+
+    /// The correlation over the window.
+    pub fn value(&self) -> f32 {
+        let [cross, left, right] = self.sums;
+        // Rounding errors can leave the sums of squares slightly negative after silence
+        let energy = (left.max(0.0) * right.max(0.0)).sqrt();
+        if energy > 1e-12 {
+            (cross / energy).clamp(-1.0, 1.0) as f32
+        } else {
+            0.0
+        }
+    }
+}
+
+/// The most makeup gain `GainMatch` applies, in decibels. Without a limit, a gate that stays
+/// closed would have its makeup gain grow without bounds.
+const MAX_GAIN_MATCH_DB: f32 = 24.0;
+
+/// Tracks the average power of a gain over a long window, and computes the makeup gain that brings
+/// a signal with that gain applied back to its original level.
+pub struct GainMatch {
+    coef: f32,
+    mean_square: f32,
+}
+
+impl GainMatch {
+    pub fn new(window_ms: f32, sample_rate: f32) -> Self {
+        Self {
+            coef: (-1000.0 / (window_ms * sample_rate)).exp(),
+            mean_square: 1.0,
+        }
+    }
+
+    /// Adds the next sample's `gain` to the average and returns the makeup gain to apply on top
+    /// of it.
+    pub fn process(&mut self, gain: f32) -> f32 {
+        let square = gain * gain;
+        self.mean_square = square + self.coef * (self.mean_square - square);
+
+        self.mean_square
+            .sqrt()
+            .recip()
+            .min(util::db_to_gain(MAX_GAIN_MATCH_DB))
+    }
+
+    /// Forgets the average, so the makeup gain starts from unity again.
+    pub fn reset(&mut self) {
+        self.mean_square = 1.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::Rng;
+
+    #[test]
+    fn channel_peaks_are_tracked_separately() {
+        let mut peaks = [0.0; 2];
+        for i in 0..4800 {
+            let sine = (i as f32 * 0.05).sin();
+            peaks[0] = decay_peak(peaks[0], (sine * 0.8).abs(), 0.999);
+            peaks[1] = decay_peak(peaks[1], (sine * 0.1).abs(), 0.999);
+        }
+
+        let channel_peaks = ChannelPeaks::default();
+        channel_peaks.store(&peaks);
+        let loaded = channel_peaks.load();
+        assert_eq!(loaded.len(), 2);
+        assert!(loaded[0] > 0.7 && loaded[0] <= 0.8, "{loaded:?}");
+        assert!(loaded[1] > 0.08 && loaded[1] <= 0.1, "{loaded:?}");
+
+        // A mono layout only reports a single channel
+        channel_peaks.store(&peaks[..1]);
+        assert_eq!(channel_peaks.load(), vec![loaded[0]]);
+    }
+
+    #[test]
+    fn reset_clears_all_channels() {
+        let channel_peaks = ChannelPeaks::default();
+        channel_peaks.store(&[0.5, 0.25, 0.125, 0.0625]);
+        channel_peaks.reset();
+        assert!(channel_peaks.load().is_empty());
+        assert_eq!(channel_peaks.load_all(), [0.0; MAX_METER_CHANNELS]);
+    }
+
+    #[test]
+    fn correlation_of_related_and_unrelated_signals() {
+        // The window covers exactly ten periods, so the sine and cosine are fully unrelated
+        let signal = |i: usize| (i as f32 * std::f32::consts::TAU / 100.0).sin();
+        let quadrature = |i: usize| (i as f32 * std::f32::consts::TAU / 100.0).cos();
+        let correlation_of = |right: &dyn Fn(usize) -> f32| {
+            let mut correlation = Correlation::new(1000);
+            for i in 0..3000 {
+                correlation.push(signal(i), right(i));
+            }
+            correlation.value()
+        };
+
+        let correlated = correlation_of(&signal);
+        let decorrelated = correlation_of(&quadrature);
+        let anti_correlated = correlation_of(&|i| -signal(i));
+        assert!((correlated - 1.0).abs() < 1e-4, "{correlated}");
+        assert!(decorrelated.abs() < 1e-3, "{decorrelated}");
+        assert!((anti_correlated + 1.0).abs() < 1e-4, "{anti_correlated}");
+    }
+
+    #[test]
+    fn silence_has_no_correlation() {
+        let mut correlation = Correlation::new(100);
+        for i in 0..100 {
+            correlation.push(i as f32 / 100.0, i as f32 / 100.0);
+        }
+        assert!(correlation.value() > 0.99);
+
+        for _ in 0..100 {
+            correlation.push(0.0, 0.0);
+        }
+        assert_eq!(correlation.value(), 0.0);
+    }
+
+    #[test]
+    fn peak_hold_stays_up_for_hold_time() {
+        // 50 ms at 48 kHz
+        let hold_samples = 2400;
+        let mut hold = PeakHold::default();
+        assert_eq!(hold.process(0.8, hold_samples, 0.999), 0.8);
+
+        for _ in 0..hold_samples {
+            assert_eq!(hold.process(0.0, hold_samples, 0.999), 0.8);
+        }
+
+        let value = hold.process(0.0, hold_samples, 0.999);
+        assert!(value < 0.8, "{value}");
+    }
+
+    #[test]
+    fn louder_peak_restarts_hold() {
+        let mut hold = PeakHold::default();
+        hold.process(0.5, 10, 0.5);
+        for _ in 0..5 {
+            hold.process(0.0, 10, 0.5);
+        }
+        assert_eq!(hold.process(0.9, 10, 0.5), 0.9);
+
+        for _ in 0..10 {
+            assert_eq!(hold.process(0.2, 10, 0.5), 0.9);
+        }
+        assert!(hold.process(0.2, 10, 0.5) < 0.9);
+    }
+
+    #[test]
+    fn gain_match_restores_rms() {
+        let sample_rate = 48000.0;
+        let mut gain_match = GainMatch::new(2000.0, sample_rate);
+        let mut rng = Rng::new(1);
+        let mut input_sum = 0.0;
+        let mut output_sum = 0.0;
+        for i in 0..480000 {
+            // A duck down to -12 dB for the first half of every 500 ms cycle
+            let gain = if i % 24000 < 12000 { 0.25 } else { 1.0 };
+            let makeup = gain_match.process(gain);
+            let input = rng.range(-1.0, 1.0);
+            let output = input * gain * makeup;
+            // The last four seconds are measured, after the average has settled
+            if i >= 288000 {
+                input_sum += input as f64 * input as f64;
+                output_sum += output as f64 * output as f64;
+            }
+        }
+
+        let difference_db = 10.0 * (output_sum / input_sum).log10();
+        assert!(difference_db.abs() < 0.5, "{difference_db}");
+    }
+}
@@ -0,0 +1,287 @@
+use nih_plug::prelude::*;
+use std::f32::consts::PI;
+
+/// The number of input samples each polyphase branch of the anti-aliasing filters covers. Both
+/// filters are linear phase, so together they delay the signal by exactly this many samples at
+/// the original sample rate regardless of the oversampling factor.
+const TAPS_PER_PHASE: usize = 16;
+
+/// The latency in samples that oversampling adds.
+pub const OVERSAMPLING_LATENCY: u32 = TAPS_PER_PHASE as u32;
+
+/// How much the ducking gain stage is oversampled by.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Oversampling {
+    #[id = "off"]
+    #[name = "Off"]
+    Off,
+    #[id = "2x"]
+    #[name = "2x"]
+    X2,
+    #[id = "4x"]
+    #[name = "4x"]
+    X4,
+}
+
+impl Oversampling {
+    /// The latency in samples this setting adds.
+    pub fn latency_samples(self) -> u32 {
+        match self {
+            Oversampling::Off => 0,
+            Oversampling::X2 | Oversampling::X4 => OVERSAMPLING_LATENCY,
+        }
+    }
+}
+
+/// A windowed sinc lowpass used for both interpolating and decimating at a single oversampling
+/// factor, along with the history for both directions.
+struct OversamplingStage {
+    factor: usize,
+    /// The filter kernel, zero padded to `(TAPS_PER_PHASE + 1) * factor` taps so every polyphase
+    /// branch has the same length.
+    kernel: Vec<f32>,
+    /// The most recent input samples at the original rate, newest first.
+    up_history: Vec<f32>,
+    /// A ring buffer of the most recent gain-modulated samples at the oversampled rate.
+    down_history: Vec<f32>,
+    down_pos: usize,
+}
+
+impl OversamplingStage {
+    fn new(factor: usize) -> Self {
+        let num_taps = TAPS_PER_PHASE * factor + 1;
+        let center = (num_taps - 1) as f32 / 2.0;
+        let cutoff = 0.5 / factor as f32;
+
+        let mut kernel = vec![0.0; (TAPS_PER_PHASE + 1) * factor];
+        for (n, tap) in kernel.iter_mut().enumerate().take(num_taps) {
+            let t = n as f32 - center;
+            let sinc = if t == 0.0 {
+                2.0 * cutoff
+            } else {
+                (2.0 * PI * cutoff * t).sin() / (PI * t)
+            };
+            let x = n as f32 / (num_taps - 1) as f32;
+            let window = 0.42 - 0.5 * (2.0 * PI * x).cos() + 0.08 * (4.0 * PI * x).cos();
+            *tap = sinc * window;
+        }
+        let sum: f32 = kernel.iter().sum();
+        for tap in &mut kernel {
+            *tap /= sum;
+        }
+
+        Self {
+            factor,
+            kernel,
+            up_history: vec![0.0; TAPS_PER_PHASE + 1],
+            down_history: vec![0.0; num_taps],
+            down_pos: 0,
+        }
+    }
+
+    /// Upsamples `input`, multiplies it by a gain ramping from `start_gain` to `end_gain` over
+    /// the oversampled samples, and decimates the result back to the original rate.
+    fn process(&mut self, input: f32, start_gain: f32, end_gain: f32) -> f32 {
+        self.up_history.rotate_right(1);
+        self.up_history[0] = input;
+
+        for phase in 0..self.factor {
+            // Zero stuffing loses `factor` worth of energy, which the filter's gain makes up for
+            let upsampled = self
+                .up_history
+                .iter()
+                .enumerate()
+                .map(|(j, x)| self.kernel[phase + j * self.factor] * x)
+                .sum::<f32>()
+                * self.factor as f32;
+
+            let t = (phase + 1) as f32 / self.factor as f32;
+            let gain = start_gain + (end_gain - start_gain) * t;
+
+            self.down_history[self.down_pos] = upsampled * gain;
+            self.down_pos = (self.down_pos + 1) % self.down_history.len();
+        }
+
+        // `down_pos` now points at the oldest sample in the ring buffer
+        let len = self.down_history.len();
+        (0..len)
+            .map(|i| self.kernel[i] * self.down_history[(self.down_pos + len - 1 - i) % len])
+            .sum()
+    }
+
+    fn reset(&mut self) {
+        self.up_history.fill(0.0);
+        self.down_history.fill(0.0);
+        self.down_pos = 0;
+    }
+}
+
+/// Oversamples the ducking gain stage for a single channel. All buffers are allocated up front so
+/// switching factors doesn't allocate on the audio thread.
+pub struct Oversampler {
+    x2: OversamplingStage,
+    x4: OversamplingStage,
+    /// Delays the dry signal by `OVERSAMPLING_LATENCY` so it stays aligned with the wet signal.
+    dry_delay: Vec<f32>,
+    dry_pos: usize,
+}
+
+impl Default for Oversampler {
+    fn default() -> Self {
+        Self {
+            x2: OversamplingStage::new(2),
+            x4: OversamplingStage::new(4),
+            dry_delay: vec![0.0; TAPS_PER_PHASE],
+            dry_pos: 0,
+        }
+    }
+}
+
+impl Oversampler {
+    /// Applies a gain that ramps from `start_gain` to `end_gain` over the sample at the
+    /// oversampled rate. With oversampling turned off this just applies `end_gain`.
+    pub fn process(
+        &mut self,
+        oversampling: Oversampling,
+        input: f32,
+        start_gain: f32,
+        end_gain: f32,
+    ) -> f32 {
+        match oversampling {
+            Oversampling::Off => input * end_gain,
+            Oversampling::X2 => self.x2.process(input, start_gain, end_gain),
+            Oversampling::X4 => self.x4.process(input, start_gain, end_gain),
+        }
+    }
+
+    /// Delays the dry signal by the same amount as `process()` delays the wet signal.
+    pub fn delay_dry(&mut self, oversampling: Oversampling, dry: f32) -> f32 {
+        if oversampling == Oversampling::Off {
+            return dry;
+        }
+
+        let delayed = self.dry_delay[self.dry_pos];
+        self.dry_delay[self.dry_pos] = dry;
+        self.dry_pos = (self.dry_pos + 1) % self.dry_delay.len();
+
+        delayed
+    }
+
+    /// Clears the filter and delay histories. Used when the oversampling factor changes so the
+    /// previous factor's stale history isn't heard when switching back to it.
+    pub fn reset(&mut self) {
+        self.x2.reset();
+        self.x4.reset();
+        self.dry_delay.fill(0.0);
+        self.dry_pos = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The number of samples the spectrum is measured over.
+    const NUM_SAMPLES: usize = 4096;
+    /// The gain ramps up over this many samples and then jumps back down.
+    const RAMP_PERIOD: usize = 32;
+
+    /// Runs a sine at `sine_bin` cycles per `NUM_SAMPLES` through `oversampling` while the gain
+    /// repeatedly ramps from 0 to 1 over `RAMP_PERIOD` samples.
+    fn ramped_sine(oversampling: Oversampling, sine_bin: usize) -> Vec<f32> {
+        let gain = |n: usize| (n % RAMP_PERIOD) as f32 / RAMP_PERIOD as f32;
+        let mut oversampler = Oversampler::default();
+        // The filters' transients are skipped
+        let skip = 256;
+        (0..skip + NUM_SAMPLES)
+            .map(|n| {
+                let angle = 2.0 * PI * ((sine_bin * n) % NUM_SAMPLES) as f32 / NUM_SAMPLES as f32;
+                let input = angle.sin();
+                let start_gain = gain(n + RAMP_PERIOD - 1);
+                oversampler.process(oversampling, input, start_gain, gain(n))
+            })
+            .skip(skip)
+            .collect()
+    }
+
+    /// The power in every `step`th bin from `first_bin` up to the Nyquist frequency.
+    fn bins_power(signal: &[f32], first_bin: usize, step: usize) -> f32 {
+        (first_bin..NUM_SAMPLES / 2)
+            .step_by(step)
+            .map(|bin| {
+                let (re, im) = signal
+                    .iter()
+                    .enumerate()
+                    .fold((0.0, 0.0), |(re, im), (n, x)| {
+                        let angle =
+                            2.0 * PI * ((bin * n) % NUM_SAMPLES) as f32 / NUM_SAMPLES as f32;
+                        (re + x * angle.cos(), im + x * angle.sin())
+                    });
+                re * re + im * im
+            })
+            .sum()
+    }
+
+    /// The ratio between the power of the components that folded back from above the Nyquist
+    /// frequency and the power of the ones that didn't, in decibels.
+    fn aliasing_db(oversampling: Oversampling) -> f32 {
+        // The sine and the gain's harmonics only produce components `sine_bin` plus a multiple of
+        // `harmonic_step` away from zero. Everything that folded back around the Nyquist
+        // frequency lands on the bins mirrored around it, which don't overlap with those.
+        let sine_bin = 1521;
+        let harmonic_step = NUM_SAMPLES / RAMP_PERIOD;
+        let output = ramped_sine(oversampling, sine_bin);
+        let direct = bins_power(&output, sine_bin % harmonic_step, harmonic_step);
+        let aliased = bins_power(
+            &output,
+            (NUM_SAMPLES - sine_bin) % harmonic_step,
+            harmonic_step,
+        );
+
+        10.0 * (aliased / direct).log10()
+    }
+
+    #[test]
+    fn oversampling_reduces_aliasing() {
+        let off_db = aliasing_db(Oversampling::Off);
+        let x2_db = aliasing_db(Oversampling::X2);
+        let x4_db = aliasing_db(Oversampling::X4);
+        assert!(x2_db < off_db - 3.0, "{x2_db} vs {off_db}");
+        assert!(x4_db < off_db - 3.0, "{x4_db} vs {off_db}");
+        assert!(x4_db < x2_db, "{x4_db} vs {x2_db}");
+    }
+
+    #[test]
+    fn dry_delay_matches_latency() {
+        for oversampling in [Oversampling::X2, Oversampling::X4] {
+            let mut oversampler = Oversampler::default();
+            let impulse = |n: usize| if n == 0 { 1.0 } else { 0.0 };
+            let dry: Vec<f32> = (0..64)
+                .map(|n| oversampler.delay_dry(oversampling, impulse(n)))
+                .collect();
+            let dry_peak = dry.iter().position(|&x| x == 1.0);
+            assert_eq!(dry_peak, Some(OVERSAMPLING_LATENCY as usize));
+            assert_eq!(
+                oversampling.latency_samples(),
+                OVERSAMPLING_LATENCY,
+                "{oversampling:?}"
+            );
+
+            // The wet signal's impulse response peaks within a sample of the dry signal
+            let mut oversampler = Oversampler::default();
+            let wet: Vec<f32> = (0..64)
+                .map(|n| oversampler.process(oversampling, impulse(n), 1.0, 1.0))
+                .collect();
+            let wet_peak = (0..wet.len())
+                .max_by(|&a, &b| wet[a].abs().total_cmp(&wet[b].abs()))
+                .unwrap();
+            assert!(
+                wet_peak.abs_diff(OVERSAMPLING_LATENCY as usize) <= 1,
+                "{oversampling:?}: {wet_peak}"
+            );
+        }
+
+        let mut oversampler = Oversampler::default();
+        assert_eq!(oversampler.delay_dry(Oversampling::Off, 1.0), 1.0);
+    }
+}
@@ -0,0 +1,213 @@
+use nih_plug::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+/// Parameters that are left alone when loading a preset. A preset that bypasses the plugin or that
+/// only monitors part of the signal would look like it simply didn't load.
+const EXCLUDED_PARAMS: &[&str] = &["bypass", "listen", "test_tone"];
+
+/// A set of parameter values that can be saved to and loaded from a JSON file. The values are
+/// stored normalized and keyed by the same parameter ids used in the plugin's state, so presets
+/// work the same in every host.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Preset {
+    pub params: BTreeMap<String, f32>,
+}
+
+impl Preset {
+    /// Captures the current values of all of `params`' parameters.
+    pub fn from_params(params: &impl Params) -> Self {
+        let params = params
+            .param_map()
+            .into_iter()
+            .filter(|(id, _, _)| !EXCLUDED_PARAMS.contains(&id.as_str()))
+            // SAFETY: The parameter pointers are owned by `params`, which outlives this call
+            .map(|(id, param_ptr, _)| (id, unsafe { param_ptr.unmodulated_normalized_value() }))
+            .collect();
+
+        Self { params }
+    }
+
+    /// Reads a preset from a JSON file.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    /// Writes the preset to a JSON file, replacing the file if it already exists.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let file = File::create(path)?;
+        Ok(serde_json::to_writer_pretty(file, self)?)
+    }
+
+    /// Sets `params`' parameters to the preset's values. Parameters the preset doesn't contain
+    /// keep their current values, and unknown ids are ignored.
+    pub fn apply(&self, params: &impl Params, setter: &ParamSetter) {
+        for (id, param_ptr, _) in params.param_map() {
+            if EXCLUDED_PARAMS.contains(&id.as_str()) {
+                continue;
+            }
+
+            if let Some(&value) = self.params.get(&id) {
+                if !value.is_finite() {
+                    continue;
+                }
+
+                // SAFETY: The parameter pointers are owned by `params`, which outlives this call
+                unsafe { set_normalized(setter, param_ptr, value) };
+            }
+        }
+    }
+}
+
+/// A built-in preset. Unlike saved presets these use plain values so they're easy to read and
+/// don't change meaning if a parameter's range changes. Enum parameters use the variant's index,
+/// and boolean parameters use 0 and 1.
+pub struct FactoryPreset {
+    pub name: &'static str,
+    pub params: &'static [(&'static str, f32)],
+}
+
+pub const FACTORY_PRESETS: &[FactoryPreset] = &[
+    FactoryPreset {
+        name: "Off",
+        params: &[
+            ("mode", 0.0),
+            ("amount", 0.0),
+            ("sidechain", 0.0),
+            ("invert", 0.0),
+        ],
+    },
+    FactoryPreset {
+        name: "Subtle Pump",
+        params: &[
+            ("mode", 0.0),
+            ("lenght", 1.0),
+            ("division", 2.0),
+            ("shape", 0.0),
+            ("pump", 10.0),
+            ("amount", 0.3),
+            ("depth", 50.0),
+            ("hold", 0.0),
+            ("sidechain", 0.0),
+            ("invert", 0.0),
+        ],
+    },
+    FactoryPreset {
+        name: "Hard Sidechain",
+        params: &[
+            ("mode", 0.0),
+            ("sidechain", 1.0),
+            ("pump", 10.0),
+            ("amount", 1.0),
+            ("depth", 50.0),
+            ("attack", 1.0),
+            ("release", 150.0),
+            ("invert", 0.0),
+        ],
+    },
+    FactoryPreset {
+        name: "Tremolo",
+        params: &[
+            ("mode", 2.0),
+            ("lenght", 1.0),
+            ("division", 3.0),
+            ("tremolo_shape", 2.0),
+            ("tremolo_depth", 6.0),
+            ("sidechain", 0.0),
+            ("invert", 0.0),
+        ],
+    },
+];
+
+impl FactoryPreset {
+    /// Sets `params`' parameters to the preset's values.
+    pub fn apply(&self, params: &impl Params, setter: &ParamSetter) {
+        for (id, param_ptr, _) in params.param_map() {
+            if let Some(&(_, value)) = self.params.iter().find(|(preset_id, _)| *preset_id == id) {
+                // SAFETY: The parameter pointers are owned by `params`, which outlives this call
+                unsafe {
+                    let normalized = param_ptr.preview_normalized(value);
+                    set_normalized(setter, param_ptr, normalized);
+                }
+            }
+        }
+    }
+}
+
+/// Sets all of `params`' parameters back to their default values, like loading an empty preset.
+/// Only the bypass is left alone, so resetting also turns off the test tone and the listen modes.
+/// The reset is a single gesture, so hosts can undo it in one step.
+pub fn reset_to_defaults(params: &impl Params, setter: &ParamSetter) {
+    let defaults: Vec<(ParamPtr, f32)> = params
+        .param_map()
+        .into_iter()
+        .filter(|(id, _, _)| id != "bypass")
+        // SAFETY: The parameter pointers are owned by `params`, which outlives this call
+        .map(|(_, param_ptr, _)| (param_ptr, unsafe { param_ptr.default_normalized_value() }))
+        .collect();
+
+    // SAFETY: The parameter pointers are owned by `params`, which outlives this call
+    unsafe { set_normalized_in_one_gesture(setter, &defaults) };
+}
+
+/// Sets several parameters to normalized values, keyed by the same ids presets use. All of the
+/// changes are made within a single gesture, so hosts can undo them in one step. Returns the ids
+/// that don't belong to any parameter, which are otherwise ignored.
+pub fn set_many(
+    params: &impl Params,
+    setter: &ParamSetter,
+    values: &[(String, f32)],
+) -> Vec<String> {
+    let param_map = params.param_map();
+    let mut changes = Vec::new();
+    let mut unknown_ids = Vec::new();
+    for (id, value) in values {
+        match param_map.iter().find(|(param_id, _, _)| param_id == id) {
+            Some(&(_, param_ptr, _)) if value.is_finite() => changes.push((param_ptr, *value)),
+            Some(_) => (),
+            None => unknown_ids.push(id.clone()),
+        }
+    }
+
+    // SAFETY: The parameter pointers are owned by `params`, which outlives this call
+    unsafe { set_normalized_in_one_gesture(setter, &changes) };
+
+    unknown_ids
+}
+
+/// Sets several parameters to normalized values within a single gesture, so hosts can undo all of
+/// the changes in one step.
+///
+/// # Safety
+///
+/// The parameter pointers must point to parameters that are still alive.
+unsafe fn set_normalized_in_one_gesture(setter: &ParamSetter, changes: &[(ParamPtr, f32)]) {
+    for &(param_ptr, _) in changes {
+        setter.raw_context.raw_begin_set_parameter(param_ptr);
+    }
+    for &(param_ptr, normalized) in changes {
+        setter
+            .raw_context
+            .raw_set_parameter_normalized(param_ptr, normalized.clamp(0.0, 1.0));
+    }
+    for &(param_ptr, _) in changes {
+        setter.raw_context.raw_end_set_parameter(param_ptr);
+    }
+}
+
+/// Sets a parameter to a normalized value with a full begin/set/end gesture.
+///
+/// # Safety
+///
+/// `param_ptr` must point to a parameter that is still alive.
+unsafe fn set_normalized(setter: &ParamSetter, param_ptr: ParamPtr, normalized: f32) {
+    setter.raw_context.raw_begin_set_parameter(param_ptr);
+    setter
+        .raw_context
+        .raw_set_parameter_normalized(param_ptr, normalized.clamp(0.0, 1.0));
+    setter.raw_context.raw_end_set_parameter(param_ptr);
+}
@@ -0,0 +1,67 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A small SplitMix64 random number generator. This is only used for randomizing patches, so it
+/// doesn't need to be cryptographically secure, just reproducible for a given seed.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Seeds the generator from the current time.
+    pub fn from_time() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or(0);
+
+        Self::new(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A uniformly distributed value between `min` and `max`.
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        // The top 24 bits fit in an `f32`'s mantissa
+        let unit = (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32;
+        min + (max - min) * unit
+    }
+
+    /// A uniformly distributed index in `0..len`. `len` must not be zero.
+    pub fn index(&mut self, len: usize) -> usize {
+        (self.next_u64() % len as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_values() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        for _ in 0..16 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+    }
+
+    #[test]
+    fn range_stays_in_bounds() {
+        let mut rng = Rng::new(7);
+        for _ in 0..1000 {
+            let value = rng.range(0.3, 1.0);
+            assert!((0.3..=1.0).contains(&value), "{value}");
+            assert!(rng.index(5) < 5);
+        }
+    }
+}
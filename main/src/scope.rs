@@ -0,0 +1,58 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::meter::AtomicF32;
+
+/// The number of points in a single frame of the output scope.
+pub const SCOPE_POINTS: usize = 64;
+/// The number of samples each point of the scope covers.
+pub const SCOPE_DECIMATION: usize = 16;
+/// The number of frames the ring buffer holds. When the editor falls behind, the oldest frames
+/// are dropped.
+const SCOPE_FRAMES: usize = 16;
+
+/// A fixed size ring buffer of peak decimated output frames, written by the audio thread and read
+/// by the editor without locking or allocating. A frame that gets overwritten while the editor is
+/// reading it may come out torn, which is harmless for a display.
+pub struct Scope {
+    frames: Vec<AtomicF32>,
+    /// The total number of frames written so far. The frame with index `i` lives in slot
+    /// `i % SCOPE_FRAMES`.
+    frames_written: AtomicUsize,
+}
+
+impl Default for Scope {
+    fn default() -> Self {
+        Self {
+            frames: (0..SCOPE_FRAMES * SCOPE_POINTS)
+                .map(|_| AtomicF32::new(0.0))
+                .collect(),
+            frames_written: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Scope {
+    /// Writes a frame to the ring buffer, overwriting the oldest frame if the buffer is full.
+    pub fn push(&self, frame: &[f32; SCOPE_POINTS]) {
+        let index = self.frames_written.load(Ordering::Relaxed);
+        let slot = &self.frames[(index % SCOPE_FRAMES) * SCOPE_POINTS..][..SCOPE_POINTS];
+        for (point, &value) in slot.iter().zip(frame) {
+            point.store(value);
+        }
+
+        self.frames_written.store(index + 1, Ordering::Release);
+    }
+
+    /// Appends the points of all frames written since `frames_read` to `points`, and updates
+    /// `frames_read`. Frames that have already been overwritten are skipped.
+    pub fn drain(&self, frames_read: &mut usize, points: &mut Vec<f32>) {
+        let frames_written = self.frames_written.load(Ordering::Acquire);
+        let first_frame = (*frames_read).max(frames_written.saturating_sub(SCOPE_FRAMES));
+        for index in first_frame..frames_written {
+            let slot = &self.frames[(index % SCOPE_FRAMES) * SCOPE_POINTS..][..SCOPE_POINTS];
+            points.extend(slot.iter().map(AtomicF32::load));
+        }
+
+        *frames_read = frames_written;
+    }
+}
@@ -0,0 +1,304 @@
+use nih_plug::prelude::*;
+
+/// How the envelope's `amount`, `release_pow`, and `depth_db` parameters move towards a new value.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmoothStyle {
+    /// Jumps straight to the new value. This is the most responsive, but can cause zipper noise.
+    #[id = "none"]
+    #[name = "None"]
+    None,
+    /// Moves at a constant rate.
+    #[id = "linear"]
+    #[name = "Linear"]
+    Linear,
+    /// Moves at a constant rate in the logarithmic domain, so it spends longer at small values.
+    #[id = "log"]
+    #[name = "Log"]
+    Log,
+    /// Covers most of the distance early on and then slows down as it approaches the new value.
+    #[id = "exp"]
+    #[name = "Exp"]
+    Exp,
+}
+
+/// Ramps a gain towards its target over a number of samples that can change at runtime. The ramp
+/// is logarithmic like nih-plug's gain smoothers, so it moves at a constant rate in decibels.
+pub struct GainSmoother {
+    current: f32,
+    target: f32,
+    /// The factor `current` is multiplied by every sample until it reaches the target.
+    step: f32,
+    remaining: usize,
+}
+
+impl GainSmoother {
+    pub fn new(value: f32) -> Self {
+        Self {
+            current: value,
+            target: value,
+            step: 1.0,
+            remaining: 0,
+        }
+    }
+
+    /// Returns the next smoothed value. When `target` differs from the previous target, a new
+    /// ramp starts from the current value that reaches `target` in `ramp_samples` samples. Both
+    /// gains need to be positive.
+    pub fn next(&mut self, target: f32, ramp_samples: usize) -> f32 {
+        if target != self.target {
+            self.target = target;
+            self.remaining = ramp_samples;
+            if ramp_samples > 0 {
+                self.step = (target / self.current).powf((ramp_samples as f32).recip());
+            }
+        }
+
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            self.current *= self.step;
+        }
+        // This also removes any rounding errors accumulated during the ramp
+        if self.remaining == 0 {
+            self.current = self.target;
+        }
+
+        self.current
+    }
+
+    /// Jumps straight to `value`, cancelling any ramp in progress.
+    pub fn reset(&mut self, value: f32) {
+        *self = Self::new(value);
+    }
+}
+
+/// Ramps a parameter towards its target over a number of samples in one of the [`SmoothStyle`]s.
+/// Unlike nih-plug's smoothers, both the style and the ramp's length can change at runtime.
+pub struct ParamSmoother {
+    current: f32,
+    target: f32,
+    /// The style of the ramp in progress. Changing the style only affects the next ramp.
+    style: SmoothStyle,
+    /// The amount added to `current` every sample for linear ramps, the factor it's multiplied by
+    /// for logarithmic ramps, and the fraction of the remaining distance covered for exponential
+    /// ramps.
+    step: f32,
+    remaining: usize,
+}
+
+impl ParamSmoother {
+    pub fn new(value: f32) -> Self {
+        Self {
+            current: value,
+            target: value,
+            style: SmoothStyle::None,
+            step: 0.0,
+            remaining: 0,
+        }
+    }
+
+    /// Returns the next smoothed value. When `target` differs from the previous target, a new
+    /// ramp in `style` starts from the current value that reaches `target` in `ramp_samples`
+    /// samples. Values at or below zero can't be ramped logarithmically, so those ramps are linear
+    /// instead.
+    pub fn next(&mut self, target: f32, style: SmoothStyle, ramp_samples: usize) -> f32 {
+        if target != self.target {
+            self.target = target;
+            self.style = match style {
+                SmoothStyle::Log if self.current <= 0.0 || target <= 0.0 => SmoothStyle::Linear,
+                style => style,
+            };
+            self.remaining = if self.style == SmoothStyle::None {
+                0
+            } else {
+                ramp_samples
+            };
+            if self.remaining > 0 {
+                let steps = ramp_samples as f32;
+                self.step = match self.style {
+                    SmoothStyle::None => 0.0,
+                    SmoothStyle::Linear => (target - self.current) / steps,
+                    SmoothStyle::Log => (target / self.current).powf(steps.recip()),
+                    // Like nih-plug's exponential smoother, this gets within 0.01% of the target
+                    // by the end of the ramp
+                    SmoothStyle::Exp => 1.0 - 0.0001f32.powf(steps.recip()),
+                };
+            }
+        }
+
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            match self.style {
+                SmoothStyle::None => (),
+                SmoothStyle::Linear => self.current += self.step,
+                SmoothStyle::Log => self.current *= self.step,
+                SmoothStyle::Exp => self.current += (self.target - self.current) * self.step,
+            }
+        }
+        // This also removes any rounding errors accumulated during the ramp
+        if self.remaining == 0 {
+            self.current = self.target;
+        }
+
+        self.current
+    }
+
+    /// The value last returned by [`next()`][Self::next()].
+    pub fn previous_value(&self) -> f32 {
+        self.current
+    }
+
+    /// Jumps straight to `value`, cancelling any ramp in progress.
+    pub fn reset(&mut self, value: f32) {
+        *self = Self::new(value);
+    }
+}
+
+/// Evaluates a value once every few samples and linearly interpolates towards it in between. Each
+/// new value is reached at the end of the interval after it was evaluated, so the output lags the
+/// evaluated values by up to one interval.
+pub struct DecimatedRamp {
+    /// The most recently evaluated value.
+    target: f32,
+    /// The amount the output changes by every sample until it reaches `target`.
+    step: f32,
+    /// How many samples are left until the next evaluation.
+    remaining: usize,
+}
+
+impl DecimatedRamp {
+    pub fn new(value: f32) -> Self {
+        Self {
+            target: value,
+            step: 0.0,
+            remaining: 0,
+        }
+    }
+
+    /// Returns the next interpolated value, calling `evaluate` for a new value every `interval`
+    /// samples. With an interval of 1 this returns every evaluated value unchanged.
+    pub fn next(&mut self, interval: usize, evaluate: impl FnOnce() -> f32) -> f32 {
+        if self.remaining == 0 {
+            let previous = self.target;
+            self.target = evaluate();
+            self.remaining = interval.max(1);
+            self.step = (self.target - previous) / self.remaining as f32;
+        }
+
+        self.remaining -= 1;
+        self.target - self.step * self.remaining as f32
+    }
+
+    /// Jumps straight to `value`. The next call to [`next()`][Self::next()] evaluates a new value.
+    pub fn reset(&mut self, value: f32) {
+        *self = Self::new(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reaches_target_after_ramp() {
+        let mut smoother = GainSmoother::new(1.0);
+        let ramp: Vec<f32> = (0..100).map(|_| smoother.next(0.25, 100)).collect();
+
+        assert!(ramp.windows(2).all(|pair| pair[1] < pair[0]), "{ramp:?}");
+        assert!(ramp[0] < 1.0 && ramp[0] > 0.25, "{}", ramp[0]);
+        assert_eq!(ramp[99], 0.25);
+        assert_eq!(smoother.next(0.25, 100), 0.25);
+    }
+
+    #[test]
+    fn zero_length_ramp_jumps() {
+        let mut smoother = GainSmoother::new(1.0);
+        assert_eq!(smoother.next(2.0, 0), 2.0);
+    }
+
+    /// Runs a ramp from 1 to 4 over 8 samples in `style`.
+    fn step_response(style: SmoothStyle) -> Vec<f32> {
+        let mut smoother = ParamSmoother::new(1.0);
+        (0..8).map(|_| smoother.next(4.0, style, 8)).collect()
+    }
+
+    #[test]
+    fn no_smoothing_jumps() {
+        assert_eq!(step_response(SmoothStyle::None), vec![4.0; 8]);
+    }
+
+    #[test]
+    fn linear_steps_are_equal() {
+        let ramp = step_response(SmoothStyle::Linear);
+        let steps: Vec<f32> = ramp.windows(2).map(|pair| pair[1] - pair[0]).collect();
+
+        assert!((ramp[0] - 1.375).abs() < 1e-6, "{ramp:?}");
+        assert!(
+            steps.iter().all(|step| (step - 0.375).abs() < 1e-5),
+            "{ramp:?}"
+        );
+        assert_eq!(ramp[7], 4.0);
+    }
+
+    #[test]
+    fn log_ratios_are_equal() {
+        let ramp = step_response(SmoothStyle::Log);
+        let ratios: Vec<f32> = ramp.windows(2).map(|pair| pair[1] / pair[0]).collect();
+        let ratio = 4.0f32.powf(1.0 / 8.0);
+
+        assert!((ramp[0] - ratio).abs() < 1e-6, "{ramp:?}");
+        assert!(ratios.iter().all(|r| (r - ratio).abs() < 1e-5), "{ramp:?}");
+        assert_eq!(ramp[7], 4.0);
+    }
+
+    #[test]
+    fn exp_steps_shrink() {
+        let ramp = step_response(SmoothStyle::Exp);
+        let steps: Vec<f32> = ramp.windows(2).map(|pair| pair[1] - pair[0]).collect();
+
+        assert!(ramp[0] - 1.0 > 0.375 * 2.0, "{ramp:?}");
+        assert!(steps.windows(2).all(|pair| pair[1] < pair[0]), "{ramp:?}");
+        assert_eq!(ramp[7], 4.0);
+    }
+
+    #[test]
+    fn decimated_ramp_evaluates_once_per_interval() {
+        let mut ramp = DecimatedRamp::new(0.0);
+        let mut evaluations = 0;
+        let values: Vec<f32> = (0..32)
+            .map(|i| {
+                ramp.next(16, || {
+                    evaluations += 1;
+                    i as f32
+                })
+            })
+            .collect();
+
+        assert_eq!(evaluations, 2);
+        // The first interval ramps from 0 to the value evaluated at its start, and the second one
+        // from there to the value evaluated at sample 16
+        assert_eq!(values[0], 0.0);
+        assert_eq!(values[15], 0.0);
+        assert_eq!(values[16], 1.0);
+        assert_eq!(values[31], 16.0);
+        assert!(
+            values[16..].windows(2).all(|pair| pair[1] > pair[0]),
+            "{values:?}"
+        );
+    }
+
+    #[test]
+    fn decimated_ramp_with_unit_interval_is_exact() {
+        let mut ramp = DecimatedRamp::new(1.0);
+        let values: Vec<f32> = (0..4).map(|i| ramp.next(1, || i as f32 * 0.5)).collect();
+        assert_eq!(values, vec![0.0, 0.5, 1.0, 1.5]);
+    }
+
+    #[test]
+    fn log_falls_back_to_linear_at_zero() {
+        let mut smoother = ParamSmoother::new(0.0);
+        let ramp: Vec<f32> = (0..4)
+            .map(|_| smoother.next(1.0, SmoothStyle::Log, 4))
+            .collect();
+        assert_eq!(ramp, vec![0.25, 0.5, 0.75, 1.0]);
+    }
+}
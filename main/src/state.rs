@@ -0,0 +1,229 @@
+use nih_plug::prelude::*;
+use nih_plug::wrapper::state::ParamValue;
+
+use crate::envelope::{Division, Modifier, TimeSignature};
+
+/// The version of the saved state format. Bump this and add a step to `migrate_state()` whenever
+/// a change would make older states load with different settings.
+pub const STATE_VERSION: u32 = 2;
+
+/// The key the state version is persisted under.
+pub const STATE_VERSION_KEY: &str = "state-version";
+
+/// Brings a saved state from an older version of the plugin up to date before it gets loaded.
+/// Parameters that are missing from the state simply keep their default values, so this only
+/// needs to handle values whose meaning has changed.
+pub fn migrate_state(state: &mut PluginState) {
+    // States saved before the version was stored do not contain the field at all
+    let version = state
+        .fields
+        .get(STATE_VERSION_KEY)
+        .and_then(|version| serde_json::from_str::<u32>(version).ok())
+        .unwrap_or(0);
+
+    if version < 1 {
+        // The length used to always be counted in quarter notes. Pin the division to quarter
+        // notes so those projects keep their timing even if the default division changes.
+        state
+            .params
+            .entry(String::from("division"))
+            .or_insert_with(|| ParamValue::String(String::from("quarter")));
+    }
+
+    if version < 2 {
+        // The free run toggle has been replaced by the sync mode. Its rate used to be in beats
+        // per second, while the new free running mode's rate is in whole cycles per second.
+        if let Some(ParamValue::Bool(true)) = state.params.remove("free_run") {
+            state.params.insert(
+                String::from("sync_mode"),
+                ParamValue::String(String::from("free_hz")),
+            );
+
+            let cycle_beats = cycle_beats(state);
+            if let Some(ParamValue::F32(rate)) = state.params.get_mut("rate") {
+                if cycle_beats > 0.0 {
+                    *rate = (*rate / cycle_beats).clamp(0.1, 20.0);
+                }
+            }
+        }
+    }
+
+    state
+        .fields
+        .insert(String::from(STATE_VERSION_KEY), STATE_VERSION.to_string());
+}
+
+/// The length of a cycle in beats according to the parameters stored in `state`.
+fn cycle_beats(state: &PluginState) -> f32 {
+    let length = match state.params.get("lenght") {
+        Some(ParamValue::I32(length)) => *length as f32,
+        // Whole numbers read back from JSON can't be told apart from floats
+        Some(ParamValue::F32(length)) => *length,
+        _ => 0.0,
+    };
+    let division = enum_value(state, "division").unwrap_or(Division::Quarter);
+    let modifier = enum_value(state, "mod").unwrap_or(Modifier::Straight);
+
+    length * division.beats(TimeSignature::COMMON_TIME) as f32 * modifier.scale() as f32
+}
+
+/// Reads an enum parameter from `state`, which is stored by its variant's id.
+fn enum_value<T: Enum>(state: &PluginState, id: &str) -> Option<T> {
+    match state.params.get(id)? {
+        ParamValue::String(variant_id) => T::ids()?
+            .iter()
+            .position(|id| id == variant_id)
+            .map(T::from_index),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn state<const N: usize>(version: Option<u32>, params: [(&str, ParamValue); N]) -> PluginState {
+        PluginState {
+            version: String::new(),
+            params: params
+                .into_iter()
+                .map(|(id, value)| (id.to_string(), value))
+                .collect(),
+            fields: version
+                .map(|version| (String::from(STATE_VERSION_KEY), version.to_string()))
+                .into_iter()
+                .collect::<BTreeMap<_, _>>(),
+        }
+    }
+
+    fn string_param<'a>(state: &'a PluginState, id: &str) -> Option<&'a str> {
+        match state.params.get(id)? {
+            ParamValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn v0_pins_the_division_to_quarter_notes() {
+        let mut state = state(None, [("lenght", ParamValue::I32(2))]);
+        migrate_state(&mut state);
+
+        assert_eq!(string_param(&state, "division"), Some("quarter"));
+        assert_eq!(
+            state.fields.get(STATE_VERSION_KEY),
+            Some(&STATE_VERSION.to_string())
+        );
+    }
+
+    #[test]
+    fn v0_keeps_a_stored_division() {
+        let mut state = state(
+            None,
+            [("division", ParamValue::String(String::from("bar")))],
+        );
+        migrate_state(&mut state);
+
+        assert_eq!(string_param(&state, "division"), Some("bar"));
+    }
+
+    #[test]
+    fn v1_free_run_becomes_free_hz() {
+        let mut state = state(
+            Some(1),
+            [
+                ("free_run", ParamValue::Bool(true)),
+                ("lenght", ParamValue::I32(2)),
+                ("division", ParamValue::String(String::from("quarter"))),
+                ("rate", ParamValue::F32(4.0)),
+            ],
+        );
+        migrate_state(&mut state);
+
+        assert!(!state.params.contains_key("free_run"));
+        assert_eq!(string_param(&state, "sync_mode"), Some("free_hz"));
+        // Four beats per second over a two beat cycle is two cycles per second
+        match state.params.get("rate") {
+            Some(ParamValue::F32(rate)) => assert!((rate - 2.0).abs() < 1e-6, "{rate}"),
+            _ => panic!("the rate is missing"),
+        }
+        assert_eq!(
+            state.fields.get(STATE_VERSION_KEY),
+            Some(&STATE_VERSION.to_string())
+        );
+    }
+
+    #[test]
+    fn v1_free_run_follows_the_stored_modifier() {
+        let mut state = state(
+            Some(1),
+            [
+                ("free_run", ParamValue::Bool(true)),
+                ("lenght", ParamValue::I32(2)),
+                ("division", ParamValue::String(String::from("quarter"))),
+                ("mod", ParamValue::String(String::from("dotted"))),
+                ("rate", ParamValue::F32(6.0)),
+            ],
+        );
+        migrate_state(&mut state);
+
+        // Six beats per second over a three beat cycle is two cycles per second
+        match state.params.get("rate") {
+            Some(ParamValue::F32(rate)) => assert!((rate - 2.0).abs() < 1e-6, "{rate}"),
+            _ => panic!("the rate is missing"),
+        }
+    }
+
+    #[test]
+    fn v1_without_free_run_keeps_the_host_tempo() {
+        let mut state = state(
+            Some(1),
+            [
+                ("free_run", ParamValue::Bool(false)),
+                ("rate", ParamValue::F32(4.0)),
+            ],
+        );
+        migrate_state(&mut state);
+
+        assert!(!state.params.contains_key("sync_mode"));
+        assert!(!state.params.contains_key("division"));
+        assert!(matches!(state.params.get("rate"), Some(ParamValue::F32(rate)) if *rate == 4.0));
+    }
+
+    #[test]
+    fn round_trips_an_old_state_through_json() {
+        let state = state(
+            Some(1),
+            [
+                ("free_run", ParamValue::Bool(true)),
+                ("lenght", ParamValue::I32(2)),
+                ("rate", ParamValue::F32(4.0)),
+                ("mix", ParamValue::F32(0.5)),
+            ],
+        );
+
+        // States saved before the version field and the mix parameter existed
+        let mut json: serde_json::Value =
+            serde_json::from_str(&serde_json::to_string(&state).unwrap()).unwrap();
+        json["fields"]
+            .as_object_mut()
+            .unwrap()
+            .remove(STATE_VERSION_KEY);
+        json["params"].as_object_mut().unwrap().remove("mix");
+        let mut state: PluginState = serde_json::from_value(json).unwrap();
+        migrate_state(&mut state);
+
+        assert_eq!(
+            state.fields.get(STATE_VERSION_KEY),
+            Some(&STATE_VERSION.to_string())
+        );
+        // Missing parameters keep their defaults when the state is loaded
+        assert!(!state.params.contains_key("mix"));
+        assert_eq!(string_param(&state, "division"), Some("quarter"));
+        assert_eq!(string_param(&state, "sync_mode"), Some("free_hz"));
+        match state.params.get("rate") {
+            Some(ParamValue::F32(rate)) => assert!((rate - 2.0).abs() < 1e-6, "{rate}"),
+            _ => panic!("the rate is missing"),
+        }
+    }
+}
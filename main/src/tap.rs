@@ -0,0 +1,89 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// The number of most recent taps the tempo is averaged over.
+const MAX_TAPS: usize = 8;
+/// Taps further apart than this start a new tempo instead of continuing the previous one. This
+/// corresponds to 30 BPM.
+const TAP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Computes a tempo from the intervals between successive taps.
+#[derive(Default)]
+pub struct TapTempo {
+    taps: VecDeque<Instant>,
+}
+
+impl TapTempo {
+    /// Records a tap at `now` and returns the tempo in beats per minute, averaged over the last
+    /// `MAX_TAPS` taps. This needs at least two taps, and a tap more than `TAP_TIMEOUT` after the
+    /// previous one starts counting again from scratch.
+    pub fn tap(&mut self, now: Instant) -> Option<f64> {
+        if self
+            .taps
+            .back()
+            .is_some_and(|last| now.saturating_duration_since(*last) > TAP_TIMEOUT)
+        {
+            self.taps.clear();
+        }
+        if self.taps.len() == MAX_TAPS {
+            self.taps.pop_front();
+        }
+        self.taps.push_back(now);
+
+        let first = *self.taps.front()?;
+        let intervals = self.taps.len() - 1;
+        let interval = now.saturating_duration_since(first).as_secs_f64() / intervals as f64;
+        (interval > 0.0).then(|| 60.0 / interval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Taps `tap_tempo` at `start` plus each of the offsets in milliseconds, returning the last
+    /// result.
+    fn tap_at(tap_tempo: &mut TapTempo, start: Instant, offsets_ms: &[u64]) -> Option<f64> {
+        offsets_ms
+            .iter()
+            .map(|&ms| tap_tempo.tap(start + Duration::from_millis(ms)))
+            .last()
+            .flatten()
+    }
+
+    #[test]
+    fn needs_two_taps() {
+        let mut tap_tempo = TapTempo::default();
+        assert_eq!(tap_at(&mut tap_tempo, Instant::now(), &[0]), None);
+    }
+
+    #[test]
+    fn averages_the_intervals() {
+        let mut tap_tempo = TapTempo::default();
+        let bpm = tap_at(&mut tap_tempo, Instant::now(), &[0, 480, 1000, 1500]).unwrap();
+        assert!((bpm - 120.0).abs() < 1e-6, "{bpm}");
+    }
+
+    #[test]
+    fn only_counts_recent_taps() {
+        let mut tap_tempo = TapTempo::default();
+        // Eight taps at 60 BPM followed by eight at 120 BPM
+        let offsets: Vec<u64> = (0..8)
+            .map(|i| i * 1000)
+            .chain((1..=8).map(|i| 7000 + i * 500))
+            .collect();
+        let bpm = tap_at(&mut tap_tempo, Instant::now(), &offsets).unwrap();
+        assert!((bpm - 120.0).abs() < 1e-6, "{bpm}");
+    }
+
+    #[test]
+    fn long_pause_starts_over() {
+        let mut tap_tempo = TapTempo::default();
+        let start = Instant::now();
+        assert!(tap_at(&mut tap_tempo, start, &[0, 1000]).is_some());
+        assert_eq!(tap_at(&mut tap_tempo, start, &[5000]), None);
+
+        let bpm = tap_at(&mut tap_tempo, start, &[5250]).unwrap();
+        assert!((bpm - 240.0).abs() < 1e-6, "{bpm}");
+    }
+}